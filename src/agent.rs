@@ -0,0 +1,282 @@
+//! A tiny per-user agent caching passphrase-derived keys on a local Unix socket, so the
+//! passphrase of an encrypted profile is not asked for on every command.
+//!
+//! The agent is the `mococli agent` subcommand of the current executable. It is started
+//! on demand by [`cache_key`], answers one line based request per connection and exits
+//! once the last cached key expired:
+//!
+//! - `GET <salt>` is answered with `<key>` or an empty line,
+//! - `PUT <salt> <key> <ttl seconds>` is answered with `OK`.
+
+use crate::utils::BoxedError;
+use mococli::config::{KeySource, SecretKey};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The socket lives in a directory only the current user can access, see
+/// `unix::check_private_dir`.
+fn socket_path() -> Option<PathBuf> {
+    dirs::runtime_dir()
+        .or_else(dirs::cache_dir)
+        .map(|dir| dir.join("mococli").join("agent.sock"))
+}
+
+/// Asks for the passphrase of encrypted profiles on the terminal and caches the derived
+/// keys in the agent.
+pub(crate) struct AgentKeySource;
+
+impl KeySource for AgentKeySource {
+    fn cached_key(&self, salt: &str) -> Option<SecretKey> {
+        fetch_key(salt)
+    }
+
+    fn passphrase(&self, profile: &str) -> Result<String, BoxedError> {
+        Ok(rpassword::prompt_password(format!(
+            "Passphrase for profile '{profile}': "
+        ))?)
+    }
+
+    /// Caching is best effort, a failing agent only means asking again next time.
+    fn cache_key(&self, salt: &str, key: &SecretKey, ttl: Duration) {
+        if let Err(error) = cache_key(salt, key, ttl) {
+            log::warn!("Could not cache the passphrase in the credential agent: {error}");
+        }
+    }
+}
+
+/// Asks a running agent for the key cached for `salt`.
+pub(crate) fn fetch_key(salt: &str) -> Option<SecretKey> {
+    #[cfg(unix)]
+    {
+        unix::request(&format!("GET {salt}"))
+            .ok()
+            .and_then(|answer| unix::decode_key(&answer))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = salt;
+        None
+    }
+}
+
+/// Hands the key for `salt` to the agent for `ttl`, starting the agent if necessary.
+pub(crate) fn cache_key(salt: &str, key: &SecretKey, ttl: Duration) -> Result<(), BoxedError> {
+    #[cfg(unix)]
+    {
+        unix::cache_key(salt, key, ttl)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (salt, key, ttl);
+        Ok(())
+    }
+}
+
+/// Runs the agent until the last cached key expired.
+pub(crate) async fn serve() -> Result<(), BoxedError> {
+    #[cfg(unix)]
+    {
+        unix::serve().await
+    }
+    #[cfg(not(unix))]
+    {
+        Err("The credential agent is only available on Unix".into())
+    }
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::socket_path;
+    use crate::utils::BoxedError;
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use mococli::config::SecretKey;
+    use std::collections::HashMap;
+    use std::fs::{DirBuilder, Permissions, remove_file, set_permissions, symlink_metadata};
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+    use std::os::unix::net::UnixStream;
+    use std::os::unix::process::CommandExt;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+    use tokio::net::UnixListener;
+    use tokio::time::{Instant, sleep_until, timeout};
+
+    /// How long a freshly started agent waits for its first key.
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+    const IO_TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Fails unless `dir` is a directory owned by the current user that nobody else may
+    /// access. Otherwise others could read the keys from the socket, or plant their own.
+    fn check_private_dir(dir: &Path) -> Result<(), BoxedError> {
+        let metadata = symlink_metadata(dir)?;
+        if !metadata.is_dir() || metadata.uid() != nix::unistd::getuid().as_raw() {
+            return Err(format!("{} is not a directory of your own", dir.display()).into());
+        }
+        if metadata.mode() & 0o077 != 0 {
+            return Err(format!(
+                "{} is accessible to other users (mode {:o}), refusing to use it for the agent",
+                dir.display(),
+                metadata.mode() & 0o777
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    pub(super) fn request(line: &str) -> Result<String, BoxedError> {
+        let path = socket_path().ok_or("No runtime directory for the agent socket")?;
+        if let Some(dir) = path.parent() {
+            check_private_dir(dir)?;
+        }
+        let mut stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        writeln!(stream, "{line}")?;
+
+        let mut answer = String::new();
+        BufReader::new(stream).read_line(&mut answer)?;
+        Ok(answer.trim().to_string())
+    }
+
+    pub(super) fn decode_key(encoded: &str) -> Option<SecretKey> {
+        BASE64.decode(encoded).ok()?.try_into().ok()
+    }
+
+    pub(super) fn cache_key(salt: &str, key: &SecretKey, ttl: Duration) -> Result<(), BoxedError> {
+        let line = format!("PUT {salt} {} {}", BASE64.encode(key), ttl.as_secs());
+        if request(&line).is_ok() {
+            return Ok(());
+        }
+
+        // No agent yet: start one in its own process group so it survives this command.
+        Command::new(std::env::current_exe()?)
+            .arg("agent")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .process_group(0)
+            .spawn()?;
+
+        let mut last_error = None;
+        for _ in 0..40 {
+            std::thread::sleep(Duration::from_millis(25));
+            match request(&line) {
+                Ok(_) => return Ok(()),
+                Err(error) => last_error = Some(error),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| "The credential agent did not start".into()))
+    }
+
+    pub(super) async fn serve() -> Result<(), BoxedError> {
+        let path = socket_path().ok_or("No runtime directory for the agent socket")?;
+        let dir = path.parent().ok_or("No directory for the agent socket")?;
+        DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+        // `mode` only applies to a newly created directory, tighten an existing one of ours.
+        let metadata = symlink_metadata(dir)?;
+        if metadata.is_dir()
+            && metadata.uid() == nix::unistd::getuid().as_raw()
+            && metadata.mode() & 0o077 != 0
+        {
+            set_permissions(dir, Permissions::from_mode(0o700))?;
+        }
+        // Checked before binding, as the socket itself is only restricted afterwards.
+        check_private_dir(dir)?;
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                log::info!("An agent is already running at {}", path.display());
+                return Ok(());
+            }
+            remove_file(&path)?;
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        set_permissions(&path, Permissions::from_mode(0o600))?;
+
+        let mut keys: HashMap<String, (String, Instant)> = HashMap::new();
+        loop {
+            let deadline = keys
+                .values()
+                .map(|(_, expires_at)| *expires_at)
+                .min()
+                .unwrap_or_else(|| Instant::now() + IDLE_TIMEOUT);
+
+            tokio::select! {
+                accepted = listener.accept() => {
+                    let (stream, _) = accepted?;
+                    match timeout(IO_TIMEOUT, handle(stream, &mut keys)).await {
+                        Ok(Ok(())) => {}
+                        Ok(Err(error)) => log::debug!("Agent request failed: {error}"),
+                        Err(_) => log::debug!("Agent request timed out"),
+                    }
+                }
+                _ = sleep_until(deadline) => {
+                    let now = Instant::now();
+                    keys.retain(|_, (_, expires_at)| *expires_at > now);
+                    if keys.is_empty() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        remove_file(&path)?;
+        Ok(())
+    }
+
+    async fn handle(
+        stream: tokio::net::UnixStream,
+        keys: &mut HashMap<String, (String, Instant)>,
+    ) -> Result<(), BoxedError> {
+        let (reader, mut writer) = stream.into_split();
+        let mut line = String::new();
+        tokio::io::BufReader::new(reader)
+            .read_line(&mut line)
+            .await?;
+
+        let answer = match line.split_whitespace().collect::<Vec<_>>().as_slice() {
+            ["GET", salt] => keys
+                .get(*salt)
+                .filter(|(_, expires_at)| *expires_at > Instant::now())
+                .map(|(key, _)| key.clone())
+                .unwrap_or_default(),
+            ["PUT", salt, key, ttl] => {
+                let ttl = Duration::from_secs(ttl.parse()?);
+                keys.insert(salt.to_string(), (key.to_string(), Instant::now() + ttl));
+                "OK".to_string()
+            }
+            _ => "ERR".to_string(),
+        };
+        writer.write_all(format!("{answer}\n").as_bytes()).await?;
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::fs::remove_dir_all;
+
+        #[test]
+        fn check_private_dir_accepts_only_private_dirs() {
+            let dir = std::env::temp_dir().join(format!("mococli-agent-{}", std::process::id()));
+            let _ = remove_dir_all(&dir);
+            DirBuilder::new().mode(0o700).create(&dir).unwrap();
+            let result = |mode| {
+                set_permissions(&dir, Permissions::from_mode(mode)).unwrap();
+                check_private_dir(&dir).map_err(|error| error.to_string())
+            };
+
+            assert!(result(0o700).is_ok());
+            for mode in [0o750, 0o705, 0o777, 0o710] {
+                assert!(
+                    result(mode).is_err_and(|error| error.contains("accessible to other users")),
+                    "mode {mode:o} should be refused"
+                );
+            }
+            let _ = remove_dir_all(&dir);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use chrono::NaiveDate;
 use clap::builder::styling::{AnsiColor, Styles};
 use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
 
 /// Combines clap v3's classic green/yellow help coloring with clap v4's
 /// bold/underline emphasis: colored *and* styled section headers.
@@ -24,21 +25,68 @@ pub(crate) fn init() -> Cli {
     long_about = "A terminal-based interface for interacting with the MOCO time tracking system, allowing you to manage activities, track time, and view reports."
 )]
 #[clap(styles = HELP_STYLES)]
+#[clap(
+    after_help = "Settings are resolved in this order (highest precedence first):
+  1. Command line flags (--base-url)
+  2. Environment variables (MOCO_COMPANY, MOCO_API_KEY, MOCO_BOT_API_KEY, MOCO_USER_ID, MOCO_BASE_URL)
+  3. The selected profile (--profile, MOCOCLI_PROFILE) of the config file (--config)"
+)]
 pub(crate) struct Cli {
     #[clap(subcommand)]
     pub(crate) command: Commands,
 
     #[clap(long, help = "Enable debug logging for troubleshooting")]
     pub(crate) debug: bool,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Override the MOCO API base URL (default: https://<company>.mocoapp.com/api/v1, env: MOCO_BASE_URL)"
+    )]
+    pub(crate) base_url: Option<String>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Use the named MOCO workspace profile instead of the default one (env: MOCOCLI_PROFILE)"
+    )]
+    pub(crate) profile: Option<String>,
+
+    #[clap(
+        long,
+        global = true,
+        help = "Read and write the config file at this path instead of the OS config directory"
+    )]
+    pub(crate) config: Option<PathBuf>,
 }
 
 #[derive(Debug, Subcommand)]
 pub(crate) enum Commands {
     #[clap(
         about = "Log into MOCO",
-        long_about = "Configure your MOCO credentials, including company name, API keys, and user identification."
+        long_about = "Configure your MOCO credentials, including company name, API keys, and user identification.\nUse '--profile <name>' to add another workspace next to the existing ones.\nValues passed as flags are not prompted for, so '--company', '--api-key' and '--bot-api-key' log in without any prompt.\nBoth keys are checked against MOCO before the config is written."
     )]
-    Login,
+    Login {
+        #[clap(long, help = "Make the logged in profile the default one")]
+        set_default: bool,
+        #[clap(long, help = "Your company's MOCO subdomain, skips the prompt")]
+        company: Option<String>,
+        #[clap(
+            long,
+            help = "Your personal API key, skips the prompt ('-' reads it from stdin)"
+        )]
+        api_key: Option<String>,
+        #[clap(
+            long,
+            help = "The MOCO Bot API key, skips the prompt ('-' reads it from stdin after the personal key)"
+        )]
+        bot_api_key: Option<String>,
+        #[clap(
+            long,
+            help = "Your MOCO user id, skips identifying you via the API key"
+        )]
+        user_id: Option<i64>,
+    },
     #[clap(
         about = "List activities (Precedence as listed)",
         long_about = "View tracked activities for a specific date, day, week, or month.\nThe Precedence refers to the order in which the flag args are listed below displayed.\n'--backward' is ignored when using the '--date' flag."
@@ -61,17 +109,23 @@ pub(crate) enum Commands {
             help = "Go back in time from now based on the day, week, or month specified"
         )]
         backward: bool,
+
+        #[clap(long, help = "Only show activities with this tag")]
+        tag: Option<String>,
     },
     #[clap(
         about = "Create a new activity",
-        long_about = "Start a new time tracking entry by specifying project, task, and duration."
+        long_about = "Start a new time tracking entry by specifying project, task, and duration.\n'--project' and '--task' take an ID or a name (the project also its identifier), matched case-insensitively; a unique prefix is enough."
     )]
     New {
-        #[clap(long, help = "The ID of the project")]
-        project: Option<i64>,
+        #[clap(help = "An alias (see 'mococli alias') providing project, task and description")]
+        alias: Option<String>,
 
-        #[clap(long, help = "The ID of the task")]
-        task: Option<i64>,
+        #[clap(long, help = "The ID, identifier or name of the project")]
+        project: Option<String>,
+
+        #[clap(long, help = "The ID or name of the task")]
+        task: Option<String>,
 
         #[clap(long, help = "The number of hours to log (0 starts a timer)")]
         hours: Option<f64>,
@@ -81,10 +135,22 @@ pub(crate) enum Commands {
 
         #[clap(long, help = "A description of the work performed")]
         description: Option<String>,
+
+        #[clap(
+            long,
+            help = "Link the activity to this ticket (e.g. PROJ-123) of the project's ticket service"
+        )]
+        ticket: Option<String>,
+
+        #[clap(long, help = "A tag for the activity (e.g. on-call or travel)")]
+        tag: Option<String>,
+
+        #[clap(long, help = "Only print the ID of the created activity")]
+        quiet: bool,
     },
     #[clap(
         about = "Edit an existing activity",
-        long_about = "Modify the details of a previously recorded activity."
+        long_about = "Modify the details of a previously recorded activity.\nWithout any of '--project', '--task', '--new-date', '--hours', '--description', '--append-description', '--tag' and '--ticket' every field is prompted for, prefilled with its current value. With them, only those changes are applied without prompting."
     )]
     Edit {
         #[clap(long, help = "The date of the activity to edit (YYYY-MM-DD)")]
@@ -92,6 +158,43 @@ pub(crate) enum Commands {
 
         #[clap(long, help = "The ID of the activity to edit")]
         activity: Option<i64>,
+
+        #[clap(
+            long,
+            help = "Move the activity to this project (identifier, name or ID)"
+        )]
+        project: Option<String>,
+
+        #[clap(
+            long,
+            help = "Move the activity to this task (name or ID), in its current project unless --project is given"
+        )]
+        task: Option<String>,
+
+        #[clap(long, help = "Move the activity to this date (YYYY-MM-DD)")]
+        new_date: Option<NaiveDate>,
+
+        #[clap(long, help = "The new number of hours")]
+        hours: Option<f64>,
+
+        #[clap(long, help = "Replace the description")]
+        description: Option<String>,
+
+        #[clap(
+            long,
+            conflicts_with = "description",
+            help = "Append this text to the current description"
+        )]
+        append_description: Option<String>,
+
+        #[clap(long, help = "Replace the tag")]
+        tag: Option<String>,
+
+        #[clap(
+            long,
+            help = "Link the activity to this ticket instead (\"\" removes the link)"
+        )]
+        ticket: Option<String>,
     },
     #[clap(
         about = "Delete an activity",
@@ -129,6 +232,100 @@ pub(crate) enum Commands {
         #[clap(long, help = "Show a detailed monthly breakdown of overtime")]
         monthly: bool,
     },
+    #[clap(
+        about = "List your assigned projects and their tasks",
+        long_about = "List the projects you are assigned to with their tasks and ids, e.g. to script 'new --project --task'.\n'--search' matches the project identifier, project name and task names case-insensitively."
+    )]
+    Projects {
+        #[clap(long, help = "Only show projects or tasks containing this text")]
+        search: Option<String>,
+
+        #[clap(
+            long,
+            help = "Only show projects of customers whose name contains this text"
+        )]
+        customer: Option<String>,
+
+        #[clap(long, help = "Also show inactive projects and tasks")]
+        include_inactive: bool,
+    },
+    #[clap(
+        about = "Manage aliases for frequently used projects and tasks",
+        long_about = "Name project/task pairs you log to often and create activities with 'mococli new <alias>'.\nFlags passed to 'new' take precedence over the alias."
+    )]
+    Alias {
+        #[clap(subcommand)]
+        command: AliasCommand,
+    },
+    #[clap(
+        about = "Show and change the settings of a profile",
+        long_about = "Inspect and change the settings of the selected profile without logging in again.\nKeys are the field names of the profile in mococli.json, e.g. 'moco_bot_api_key' or 'retry_max_retries'."
+    )]
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommand,
+    },
+    #[clap(
+        hide = true,
+        about = "Run the credential agent caching unlocked passphrases",
+        long_about = "Run the credential agent caching unlocked passphrases. It is started automatically when needed and exits once the last cached passphrase expired."
+    )]
+    Agent,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum AliasCommand {
+    #[clap(about = "Add an alias, or replace the one with the same name")]
+    Add {
+        #[clap(help = "The name of the alias, e.g. standup")]
+        name: String,
+
+        #[clap(long, help = "The ID, identifier or name of the project")]
+        project: String,
+
+        #[clap(long, help = "The ID or name of the task")]
+        task: String,
+
+        #[clap(
+            long,
+            help = "The default description of activities created with the alias"
+        )]
+        description: Option<String>,
+    },
+    #[clap(about = "List all aliases")]
+    List,
+    #[clap(about = "Remove an alias")]
+    Rm {
+        #[clap(help = "The name of the alias")]
+        name: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum ConfigCommand {
+    #[clap(about = "Show all settings of the profile with the API keys masked")]
+    Show,
+    #[clap(about = "Print the value of a setting")]
+    Get {
+        #[clap(help = "The setting, e.g. moco_company")]
+        key: String,
+    },
+    #[clap(about = "Change a setting")]
+    Set {
+        #[clap(help = "The setting, e.g. moco_bot_api_key")]
+        key: String,
+        #[clap(help = "The new value")]
+        value: String,
+    },
+    #[clap(about = "Remove a setting")]
+    Unset {
+        #[clap(help = "The setting, e.g. moco_base_url")]
+        key: String,
+    },
+    #[clap(about = "Print the path of the config file")]
+    Path,
+    #[clap(about = "Check that the profile has everything needed to talk to MOCO")]
+    Validate,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -1,51 +1,833 @@
-use crate::utils::BoxedError;
-use config::Config;
+use crate::BoxedError;
+pub use crate::secrets::{EncryptedSecrets, SecretKey};
+use crate::secrets::{Secrets, derive_key};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
 use serde::{Deserialize, Serialize};
-use std::fs::{File, create_dir, write};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{File, OpenOptions, create_dir_all, read_to_string, remove_file, rename};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+/// The settings of a single MOCO workspace.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct AppConfig {
+    /// Name of the profile this config was loaded from and is written back to.
+    #[serde(skip)]
+    pub profile: String,
+    /// The file this config was loaded from and is written back to.
+    #[serde(skip)]
+    pub path: PathBuf,
+    pub moco_company: Option<String>,
+    pub moco_api_key: Option<String>,
+    pub moco_bot_api_key: Option<String>,
+    /// Credential helper printing the personal API key, used when `moco_api_key` is unset.
+    pub api_key_command: Option<String>,
+    /// Credential helper printing the bot API key, used when `moco_bot_api_key` is unset.
+    pub bot_api_key_command: Option<String>,
+    pub moco_user_id: Option<i64>,
+    /// Full name of the user `moco_user_id` belongs to, for display only.
+    pub moco_user_name: Option<String>,
+    /// The API keys encrypted with a passphrase, replacing the plaintext ones.
+    pub secrets: Option<EncryptedSecrets>,
+    /// How long the credential agent caches the unlocked passphrase key (`0` disables it).
+    pub agent_timeout_secs: Option<u64>,
+    pub moco_base_url: Option<String>,
+    pub retry_max_retries: Option<u32>,
+    pub retry_base_delay_ms: Option<u64>,
+    /// Upper limit for a single wait before a retry, whatever MOCO asks for.
+    pub retry_max_delay_ms: Option<u64>,
+    /// Named project/task pairs for `mococli new <alias>`.
+    #[serde(default)]
+    pub aliases: BTreeMap<String, Alias>,
+    /// The ticket systems `--ticket` links to, keyed by MOCO project id, with `*` for all
+    /// other projects.
+    #[serde(default)]
+    pub tickets: BTreeMap<String, TicketService>,
+}
+
+/// A project/task pair, with an optional default description, activities are often
+/// logged to. The names are the ones at the time the alias was added, for display only.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Alias {
+    pub project_id: i64,
+    pub project_name: String,
+    pub task_id: i64,
+    pub task_name: String,
+    pub description: Option<String>,
+}
+
+/// A ticket system activities are linked to through MOCO's `remote_*` fields.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TicketService {
+    /// MOCO's name for the service, e.g. `jira`, `github` or `gitlab`.
+    pub service: String,
+    /// The URL of a ticket, with `{ticket}` standing in for its id.
+    pub url: String,
+}
+
+/// Everything but the characters URLs never need escaped, so a ticket id stays one path
+/// segment or query value.
+const TICKET_ESCAPE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+impl TicketService {
+    pub fn ticket_url(&self, ticket: &str) -> String {
+        self.url.replace(
+            "{ticket}",
+            &utf8_percent_encode(ticket, TICKET_ESCAPE).to_string(),
+        )
+    }
+}
+
+/// The contents of `mococli.json`: one [`AppConfig`] per named profile.
 #[derive(Serialize, Deserialize, Clone)]
-pub(crate) struct AppConfig {
-    pub(crate) moco_company: Option<String>,
-    pub(crate) moco_api_key: Option<String>,
-    pub(crate) moco_bot_api_key: Option<String>,
-    pub(crate) moco_user_id: Option<i64>,
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// The schema version the file was written with, see [`CONFIG_VERSION`].
+    pub version: u32,
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, AppConfig>,
+}
+
+impl Default for ConfigFile {
+    fn default() -> Self {
+        ConfigFile {
+            version: CONFIG_VERSION,
+            default_profile: None,
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, derive_more::Display)]
+pub enum ConfigError {
+    #[display("{_0} must be a numeric MOCO user id, got '{_1}'")]
+    InvalidUserId(&'static str, String),
+    #[display("Unknown config key '{_0}', expected one of: {_1}")]
+    UnknownKey(String, String),
+    #[display("'{_1}' is not a valid value for {_0}")]
+    InvalidValue(String, String),
+    #[display("{_0} is managed by `{_1}` and can't be changed directly")]
+    ReadOnlyKey(String, &'static str),
+    #[display("{} is invalid: {_1}", _0.display())]
+    InvalidFile(PathBuf, serde_json::Error),
+    #[display("Can't find the OS config directory, pass the config file with --config")]
+    NoConfigDir,
+    #[display(
+        "{} has config version {_1}, but this mococli only supports up to version {}",
+        _0.display(),
+        CONFIG_VERSION
+    )]
+    UnsupportedVersion(PathBuf, u64),
+    #[display("Profile '{_0}' is encrypted, but no passphrase was given")]
+    PassphraseRequired(String),
+    #[display("Profile '{_0}' does not exist, create it with 'mococli login'")]
+    UnknownProfile(String),
+}
+impl Error for ConfigError {}
+
+/// Where the key of an encrypted profile comes from. The library itself never prompts
+/// or starts processes: applications decide how to ask for the passphrase and whether
+/// to cache the derived key.
+pub trait KeySource {
+    /// A key unlocked earlier for the secrets with `salt`, if one is cached.
+    fn cached_key(&self, _salt: &str) -> Option<SecretKey> {
+        None
+    }
+
+    /// The passphrase of the encrypted `profile`.
+    fn passphrase(&self, profile: &str) -> Result<String, BoxedError>;
+
+    /// Keeps an unlocked key for `ttl` so that `cached_key` can return it. Best effort.
+    fn cache_key(&self, _salt: &str, _key: &SecretKey, _ttl: Duration) {}
+}
+
+/// A passphrase known up front, e.g. read from a secret store.
+pub struct Passphrase(pub String);
+
+impl KeySource for Passphrase {
+    fn passphrase(&self, _profile: &str) -> Result<String, BoxedError> {
+        Ok(self.0.clone())
+    }
 }
 
-fn get_config_path() -> Option<std::path::PathBuf> {
+/// For profiles that are not encrypted: unlocking an encrypted one fails.
+pub struct NoPassphrase;
+
+impl KeySource for NoPassphrase {
+    fn passphrase(&self, profile: &str) -> Result<String, BoxedError> {
+        Err(Box::new(ConfigError::PassphraseRequired(
+            profile.to_string(),
+        )))
+    }
+}
+
+/// The schema version of `mococli.json` written by this release. Files from before
+/// versioning have none: version 0 is the flat single-workspace config, version 1
+/// added profiles.
+pub const CONFIG_VERSION: u32 = 5;
+pub const DEFAULT_PROFILE: &str = "default";
+pub const DEFAULT_AGENT_TIMEOUT_SECS: u64 = 15 * 60;
+
+const ENV_COMPANY: &str = "MOCO_COMPANY";
+const ENV_API_KEY: &str = "MOCO_API_KEY";
+const ENV_BOT_API_KEY: &str = "MOCO_BOT_API_KEY";
+const ENV_USER_ID: &str = "MOCO_USER_ID";
+const ENV_BASE_URL: &str = "MOCO_BASE_URL";
+const ENV_PROFILE: &str = "MOCOCLI_PROFILE";
+
+/// Settings kept inside `secrets` when the profile is encrypted.
+pub const ENCRYPTED_KEYS: [&str; 2] = ["moco_api_key", "moco_bot_api_key"];
+/// Settings only the given command may change.
+const READ_ONLY_KEYS: [(&str, &str); 2] =
+    [("secrets", "mococli login"), ("aliases", "mococli alias")];
+
+fn get_config_path() -> Option<PathBuf> {
     dirs::config_dir().map(|dir| dir.join("mococli").join("mococli.json"))
 }
 
-pub(crate) fn init() -> Result<AppConfig, BoxedError> {
-    let config_file = get_config_path();
-    let config_file = match config_file {
-        Some(path) => {
-            if !path.exists() {
-                if !&path.parent().unwrap().exists() {
-                    create_dir(path.parent().unwrap())?;
-                }
-                File::create(&path)?;
-                write(&path, "{}")?;
-            }
-            path
+fn unknown_key(key: &str, map: &Map<String, Value>) -> ConfigError {
+    let keys = map.keys().map(String::as_str).collect::<Vec<_>>();
+    ConfigError::UnknownKey(key.to_string(), keys.join(", "))
+}
+
+/// An environment variable's value, treating an empty variable as unset.
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Loads the requested profile like [`load`] and decrypts its encrypted API keys with the
+/// key from `key_source`.
+pub fn init(
+    path: Option<PathBuf>,
+    profile: Option<&str>,
+    key_source: &dyn KeySource,
+) -> Result<AppConfig, BoxedError> {
+    let mut app_config = load(path, profile)?;
+    app_config.unlock_secrets(key_source)?;
+    Ok(app_config)
+}
+
+/// Loads the requested profile like [`load_stored`] and applies the `MOCO_*` environment
+/// variables on top. When all credentials come from the environment, e.g. in CI jobs, the
+/// file is not read at all, so a missing or broken file doesn't matter.
+pub fn load(path: Option<PathBuf>, profile: Option<&str>) -> Result<AppConfig, BoxedError> {
+    let mut app_config = if [ENV_COMPANY, ENV_API_KEY, ENV_BOT_API_KEY, ENV_USER_ID]
+        .iter()
+        .all(|name| env_var(name).is_some())
+    {
+        AppConfig {
+            profile: profile
+                .map(str::to_string)
+                .or_else(|| env_var(ENV_PROFILE))
+                .unwrap_or_else(|| DEFAULT_PROFILE.to_string()),
+            path: path.or_else(get_config_path).unwrap_or_default(),
+            ..AppConfig::default()
         }
-        None => panic!("Can't find os config directory"),
+    } else {
+        load_stored(path, profile)?
     };
-    Ok(Config::builder()
-        .add_source(config::File::from(config_file))
-        .build()?
-        .try_deserialize::<AppConfig>()?)
+    app_config.apply_env_overrides()?;
+    Ok(app_config)
+}
+
+/// Loads the requested profile from `path` (default: `<config dir>/mococli/mococli.json`)
+/// exactly as it is stored. Without an explicit `profile` the `MOCOCLI_PROFILE`
+/// environment variable, then the file's `default_profile` is used. A missing file or a
+/// profile that does not exist yet yields an empty config which `login` can fill in.
+pub fn load_stored(path: Option<PathBuf>, profile: Option<&str>) -> Result<AppConfig, BoxedError> {
+    let config_file = path
+        .or_else(get_config_path)
+        .ok_or(ConfigError::NoConfigDir)?;
+    let file = if config_file.exists() {
+        read_config_file(&config_file)?
+    } else {
+        ConfigFile::default()
+    };
+
+    let profile = profile
+        .map(str::to_string)
+        .or_else(|| env_var(ENV_PROFILE))
+        .or(file.default_profile)
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+    let mut app_config = file.profiles.get(&profile).cloned().unwrap_or_default();
+    app_config.profile = profile;
+    app_config.path = config_file;
+    Ok(app_config)
+}
+
+/// `path` with `suffix` appended to its file name, e.g. `mococli.json.lock`.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(suffix);
+    PathBuf::from(sibling)
+}
+
+/// Replaces `path` with `contents` by writing a temp file next to it and renaming it over
+/// the original, so other instances never read a half written file. The file is readable
+/// and writable by the current user only.
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    let temp_path = sibling(path, &format!(".{}.tmp", std::process::id()));
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let result = options.open(&temp_path).and_then(|mut file| {
+        // `mode` only applies to newly created files, tighten a leftover one as well.
+        #[cfg(unix)]
+        file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        rename(&temp_path, path)
+    });
+    if result.is_err() {
+        let _ = remove_file(&temp_path);
+    }
+    result
+}
+
+/// Takes an advisory lock on `mococli.json.lock`, serializing read-modify-write cycles of
+/// `path` between `mococli` instances. The lock is released when the file is dropped.
+fn lock_config_file(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(false);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let file = options.open(sibling(path, ".lock"))?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// Reads `mococli.json`, rejecting unknown settings. Files of an older schema version are
+/// upgraded in place, keeping the original as `mococli.json.v<version>.bak` next to it.
+fn read_config_file(path: &Path) -> Result<ConfigFile, BoxedError> {
+    let (file, version) = parse_config_file(path, &read_to_string(path)?)?;
+    if version == u64::from(CONFIG_VERSION) {
+        return Ok(file);
+    }
+
+    let _lock = lock_config_file(path)?;
+    // Another instance may have upgraded the file while we waited for the lock.
+    let contents = read_to_string(path)?;
+    let (file, version) = parse_config_file(path, &contents)?;
+    if version < u64::from(CONFIG_VERSION) {
+        backup_config_file(path, &contents, version)?;
+        write_private(path, &serde_json::to_string(&file)?)?;
+    }
+    Ok(file)
+}
+
+/// Parses the `contents` of `path`, upgrading an older schema in memory. Also returns the
+/// version the file was written with.
+fn parse_config_file(path: &Path, contents: &str) -> Result<(ConfigFile, u64), BoxedError> {
+    let invalid = |error| ConfigError::InvalidFile(path.to_path_buf(), error);
+    let mut json = serde_json::from_str::<Value>(contents).map_err(invalid)?;
+
+    let version = schema_version(&json);
+    if version > u64::from(CONFIG_VERSION) {
+        return Err(Box::new(ConfigError::UnsupportedVersion(
+            path.to_path_buf(),
+            version,
+        )));
+    }
+    migrate(&mut json, version as u32);
+    Ok((serde_json::from_value(json).map_err(invalid)?, version))
+}
+
+/// Keeps the `contents` of a file about to be upgraded from `version`.
+fn backup_config_file(path: &Path, contents: &str, version: u64) -> std::io::Result<()> {
+    let backup = sibling(path, &format!(".v{version}.bak"));
+    write_private(&backup, contents)?;
+    log::info!(
+        "Upgraded the config to version {CONFIG_VERSION}, the old one was kept as {}",
+        backup.display()
+    );
+    Ok(())
+}
+
+/// The schema version of a parsed `mococli.json`, see [`CONFIG_VERSION`]. A malformed
+/// `version` counts as the current one, so parsing the file reports it.
+fn schema_version(json: &Value) -> u64 {
+    if let Some(version) = json.get("version") {
+        return version.as_u64().unwrap_or(u64::from(CONFIG_VERSION));
+    }
+    let is_flat = json
+        .as_object()
+        .is_some_and(|root| !root.is_empty() && !root.contains_key("profiles"));
+    if is_flat { 0 } else { 1 }
+}
+
+/// Upgrades `json` from `version` to [`CONFIG_VERSION`] one version at a time.
+fn migrate(json: &mut Value, version: u32) {
+    for from in version..CONFIG_VERSION {
+        match from {
+            // Move the single workspace into the default profile.
+            0 => {
+                *json = serde_json::json!({
+                    "default_profile": DEFAULT_PROFILE,
+                    "profiles": { DEFAULT_PROFILE: json.take() },
+                })
+            }
+            // Only the version itself is new.
+            1 => {}
+            // Profiles without aliases default to none.
+            2 => {}
+            // Profiles without ticket services default to none.
+            3 => {}
+            // Profiles without a retry delay limit use the default one.
+            4 => {}
+            _ => unreachable!("no migration from config version {from}"),
+        }
+        if let Some(root) = json.as_object_mut() {
+            root.insert("version".to_string(), Value::from(from + 1));
+        }
+    }
 }
 
 impl AppConfig {
-    pub(crate) fn write_config(&self) -> Result<(), BoxedError> {
-        let config_file = get_config_path();
-        match config_file {
-            Some(file) => {
-                let json_string = serde_json::to_string(self)?;
-                write(file, json_string)?;
-            }
-            None => panic!("Can't find os config directory"),
+    /// The API root every endpoint is appended to. An explicit `moco_base_url`
+    /// wins, otherwise it is derived from the company's MOCO subdomain.
+    pub fn base_url(&self) -> Option<String> {
+        match (&self.moco_base_url, &self.moco_company) {
+            (Some(base_url), _) => Some(base_url.trim_end_matches('/').to_string()),
+            (None, Some(company)) => Some(format!("https://{company}.mocoapp.com/api/v1")),
+            (None, None) => None,
+        }
+    }
+
+    fn apply_env_overrides(&mut self) -> Result<(), ConfigError> {
+        if let Some(company) = env_var(ENV_COMPANY) {
+            self.moco_company = Some(company);
+        }
+        if let Some(api_key) = env_var(ENV_API_KEY) {
+            self.moco_api_key = Some(api_key);
+        }
+        if let Some(bot_api_key) = env_var(ENV_BOT_API_KEY) {
+            self.moco_bot_api_key = Some(bot_api_key);
+        }
+        if let Some(user_id) = env_var(ENV_USER_ID) {
+            self.moco_user_id = Some(
+                user_id
+                    .parse()
+                    .map_err(|_| ConfigError::InvalidUserId(ENV_USER_ID, user_id))?,
+            );
+        }
+        if let Some(base_url) = env_var(ENV_BASE_URL) {
+            self.moco_base_url = Some(base_url);
+        }
+        Ok(())
+    }
+
+    /// Fills in the API keys from the encrypted `secrets` section unless they are already
+    /// known, e.g. from the environment.
+    fn unlock_secrets(&mut self, key_source: &dyn KeySource) -> Result<(), BoxedError> {
+        let Some(encrypted) = &self.secrets else {
+            return Ok(());
+        };
+        if self.moco_api_key.is_some() && self.moco_bot_api_key.is_some() {
+            return Ok(());
+        }
+
+        let (secrets, _) = self.decrypt_secrets(encrypted, key_source)?;
+        self.moco_api_key = self.moco_api_key.take().or(secrets.moco_api_key);
+        self.moco_bot_api_key = self.moco_bot_api_key.take().or(secrets.moco_bot_api_key);
+        Ok(())
+    }
+
+    /// Decrypts `encrypted` with a key cached by `key_source`, deriving it from the
+    /// passphrase if there is none.
+    fn decrypt_secrets(
+        &self,
+        encrypted: &EncryptedSecrets,
+        key_source: &dyn KeySource,
+    ) -> Result<(Secrets, SecretKey), BoxedError> {
+        if let Some(key) = key_source.cached_key(&encrypted.salt)
+            && let Ok(secrets) = encrypted.decrypt(&key)
+        {
+            return Ok((secrets, key));
+        }
+
+        let passphrase = key_source.passphrase(&self.profile)?;
+        let key = derive_key(&passphrase, &encrypted.salt()?)?;
+        let secrets = encrypted.decrypt(&key)?;
+        self.cache_secret_key(&encrypted.salt, &key, key_source);
+        Ok((secrets, key))
+    }
+
+    /// Hands an unlocked key to `key_source` for `agent_timeout_secs`.
+    fn cache_secret_key(&self, salt: &str, key: &SecretKey, key_source: &dyn KeySource) {
+        let timeout = self
+            .agent_timeout_secs
+            .unwrap_or(DEFAULT_AGENT_TIMEOUT_SECS);
+        if timeout != 0 {
+            key_source.cache_key(salt, key, Duration::from_secs(timeout));
+        }
+    }
+
+    /// Stores the API keys encrypted with `passphrase` in `secrets` instead of in plain
+    /// text. The keys stay available in memory, the derived key is cached by `key_source`.
+    pub fn encrypt_api_keys(
+        &mut self,
+        passphrase: &str,
+        key_source: &dyn KeySource,
+    ) -> Result<(), BoxedError> {
+        let (secrets, key) = EncryptedSecrets::encrypt(
+            &Secrets {
+                moco_api_key: self.moco_api_key.clone(),
+                moco_bot_api_key: self.moco_bot_api_key.clone(),
+            },
+            passphrase,
+        )?;
+        self.cache_secret_key(&secrets.salt, &key, key_source);
+        self.secrets = Some(secrets);
+        Ok(())
+    }
+
+    /// The value of a setting as stored in the config file, with encrypted API keys
+    /// decrypted with the key from `key_source`. Unset settings are `null`.
+    pub fn get(&self, key: &str, key_source: &dyn KeySource) -> Result<Value, BoxedError> {
+        if let Some(encrypted) = &self.secrets
+            && ENCRYPTED_KEYS.contains(&key)
+        {
+            let (secrets, _) = self.decrypt_secrets(encrypted, key_source)?;
+            return Ok(serde_json::to_value(secrets)?[key].take());
+        }
+        let mut map = self.to_map()?;
+        map.remove(key)
+            .ok_or_else(|| Box::new(unknown_key(key, &map)) as BoxedError)
+    }
+
+    /// Changes a setting, reading `value` as a string first and as JSON (e.g. a number)
+    /// second. `None` unsets it. Encrypted API keys are encrypted again right away.
+    pub fn set(
+        &mut self,
+        key: &str,
+        value: Option<&str>,
+        key_source: &dyn KeySource,
+    ) -> Result<(), BoxedError> {
+        if let Some((_, command)) = READ_ONLY_KEYS
+            .iter()
+            .find(|(read_only, _)| *read_only == key)
+        {
+            return Err(Box::new(ConfigError::ReadOnlyKey(key.to_string(), command)));
+        }
+        let mut map = self.to_map()?;
+        if !map.contains_key(key) {
+            return Err(Box::new(unknown_key(key, &map)));
+        }
+        if let Some(encrypted) = self.secrets.clone()
+            && ENCRYPTED_KEYS.contains(&key)
+        {
+            let (secrets, secret_key) = self.decrypt_secrets(&encrypted, key_source)?;
+            let mut secrets = serde_json::to_value(secrets)?;
+            secrets[key] = value.map(Value::from).unwrap_or(Value::Null);
+            self.secrets = Some(encrypted.reseal(&serde_json::from_value(secrets)?, &secret_key)?);
+            return Ok(());
+        }
+
+        let candidates = match value {
+            Some(value) => std::iter::once(Value::from(value))
+                .chain(serde_json::from_str(value).ok())
+                .map(Some)
+                .collect(),
+            // A missing setting takes its default, which is none or empty.
+            None => vec![None],
+        };
+        for candidate in candidates {
+            match candidate {
+                Some(candidate) => map.insert(key.to_string(), candidate),
+                None => map.remove(key),
+            };
+            if let Ok(mut app_config) =
+                serde_json::from_value::<AppConfig>(Value::Object(map.clone()))
+            {
+                app_config.profile = std::mem::take(&mut self.profile);
+                app_config.path = std::mem::take(&mut self.path);
+                *self = app_config;
+                return Ok(());
+            }
+        }
+        Err(Box::new(ConfigError::InvalidValue(
+            key.to_string(),
+            value.unwrap_or_default().to_string(),
+        )))
+    }
+
+    /// This config as a JSON object keyed by the setting names.
+    pub fn to_map(&self) -> Result<Map<String, Value>, BoxedError> {
+        match serde_json::to_value(self)? {
+            Value::Object(map) => Ok(map),
+            _ => unreachable!("AppConfig serializes to an object"),
+        }
+    }
+
+    /// Lists everything that keeps this profile from working, empty if it is complete.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = vec![];
+        match self.base_url() {
+            None => problems.push("moco_company (or moco_base_url) is not set".to_string()),
+            Some(base_url) => {
+                if let Err(error) = reqwest::Url::parse(&base_url) {
+                    problems.push(format!("'{base_url}' is not a valid API base URL: {error}"));
+                }
+            }
+        }
+        if self.moco_api_key.is_none() && self.api_key_command.is_none() && self.secrets.is_none() {
+            problems.push("Neither moco_api_key nor api_key_command is set".to_string());
+        }
+        if self.moco_bot_api_key.is_none()
+            && self.bot_api_key_command.is_none()
+            && self.secrets.is_none()
+        {
+            problems.push("Neither moco_bot_api_key nor bot_api_key_command is set".to_string());
+        }
+        if self.moco_user_id.is_none() {
+            problems.push("moco_user_id is not set".to_string());
+        }
+        if let Some(encrypted) = &self.secrets
+            && let Err(error) = encrypted.salt()
+        {
+            problems.push(format!("secrets are damaged: {error}"));
+        }
+        for (project, ticket_service) in &self.tickets {
+            if project != "*" && project.parse::<i64>().is_err() {
+                problems.push(format!(
+                    "tickets: '{project}' is neither a project id nor '*'"
+                ));
+            }
+            if !ticket_service.url.contains("{ticket}") {
+                problems.push(format!(
+                    "tickets.{project}: url has no {{ticket}} placeholder"
+                ));
+            } else if let Err(error) = reqwest::Url::parse(&ticket_service.ticket_url("1")) {
+                problems.push(format!("tickets.{project}: url is not valid: {error}"));
+            }
+        }
+        problems
+    }
+
+    /// The ticket system of the given project, falling back to the one for all projects.
+    pub fn ticket_service(&self, project_id: i64) -> Option<&TicketService> {
+        self.tickets
+            .get(&project_id.to_string())
+            .or_else(|| self.tickets.get("*"))
+    }
+
+    /// Stores this config as its profile, leaving all other profiles untouched.
+    /// The first profile ever written, or one written with `make_default`, becomes the default.
+    pub fn write_config(&self, make_default: bool) -> Result<(), BoxedError> {
+        self.update_file(|config_file| {
+            if make_default {
+                config_file.default_profile = Some(self.profile.clone());
+            }
+            let mut stored = self.clone();
+            // Decrypted keys stay in memory, only their encrypted form is written.
+            if stored.secrets.is_some() {
+                stored.moco_api_key = None;
+                stored.moco_bot_api_key = None;
+            }
+            config_file.profiles.insert(self.profile.clone(), stored);
+            Ok(())
+        })
+    }
+
+    /// Changes only what `update` changes in the stored version of this profile, so
+    /// environment overrides and flags used by this run don't end up in the file. The
+    /// profile must exist already, only [`AppConfig::write_config`] creates profiles.
+    pub fn update_stored(
+        &self,
+        update: impl FnOnce(&mut AppConfig) -> Result<(), BoxedError>,
+    ) -> Result<(), BoxedError> {
+        self.update_file(|config_file| {
+            let stored = config_file
+                .profiles
+                .get_mut(&self.profile)
+                .ok_or_else(|| ConfigError::UnknownProfile(self.profile.clone()))?;
+            stored.profile.clone_from(&self.profile);
+            stored.path.clone_from(&self.path);
+            update(stored)
+        })
+    }
+
+    /// Reads, updates and replaces the file this config was loaded from under its lock.
+    fn update_file(
+        &self,
+        update: impl FnOnce(&mut ConfigFile) -> Result<(), BoxedError>,
+    ) -> Result<(), BoxedError> {
+        let file = &self.path;
+        if let Some(dir) = file.parent() {
+            create_dir_all(dir)?;
+        }
+        // Held until the file is replaced, so concurrent writers don't drop each other's profiles.
+        let _lock = lock_config_file(file)?;
+        let mut config_file = if file.exists() {
+            let contents = read_to_string(file)?;
+            let (config_file, version) = parse_config_file(file, &contents)?;
+            if version < u64::from(CONFIG_VERSION) {
+                backup_config_file(file, &contents, version)?;
+            }
+            config_file
+        } else {
+            ConfigFile::default()
         };
+        update(&mut config_file)?;
+        if config_file.default_profile.is_none() {
+            config_file.default_profile = Some(self.profile.clone());
+        }
+
+        let json_string = serde_json::to_string(&config_file)?;
+        write_private(file, &json_string)?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, remove_dir_all, write};
+
+    /// A fresh `mococli.json` path in a directory of its own, removed when dropped.
+    struct TempConfig(PathBuf);
+
+    impl TempConfig {
+        fn new(name: &str, contents: Option<&str>) -> Self {
+            let dir = std::env::temp_dir().join(format!("mococli-{}-{name}", std::process::id()));
+            let _ = remove_dir_all(&dir);
+            create_dir_all(&dir).unwrap();
+            let path = dir.join("mococli.json");
+            if let Some(contents) = contents {
+                write(&path, contents).unwrap();
+            }
+            TempConfig(path)
+        }
+    }
+
+    impl Drop for TempConfig {
+        fn drop(&mut self) {
+            let _ = remove_dir_all(self.0.parent().unwrap());
+        }
+    }
+
+    const FLAT_V0: &str = r#"{"moco_company":"acme","moco_api_key":"key","moco_bot_api_key":"bot","moco_user_id":42}"#;
+
+    #[test]
+    fn flat_v0_file_moves_into_the_default_profile() {
+        let config = TempConfig::new("v0", Some(FLAT_V0));
+        let file = read_config_file(&config.0).unwrap();
+
+        assert_eq!(file.version, CONFIG_VERSION);
+        assert_eq!(file.default_profile.as_deref(), Some(DEFAULT_PROFILE));
+        let profile = &file.profiles[DEFAULT_PROFILE];
+        assert_eq!(profile.moco_company.as_deref(), Some("acme"));
+        assert_eq!(profile.moco_user_id, Some(42));
+
+        let rewritten: Value = serde_json::from_str(&read_to_string(&config.0).unwrap()).unwrap();
+        assert_eq!(rewritten["version"], Value::from(CONFIG_VERSION));
+        assert_eq!(
+            rewritten["profiles"][DEFAULT_PROFILE]["moco_company"],
+            "acme"
+        );
+    }
+
+    #[test]
+    fn v1_file_with_profiles_keeps_them() {
+        let contents = r#"{"default_profile":"work","profiles":{"work":{"moco_company":"acme"},"other":{"moco_company":"beta"}}}"#;
+        let config = TempConfig::new("v1", Some(contents));
+        let file = read_config_file(&config.0).unwrap();
+
+        assert_eq!(file.version, CONFIG_VERSION);
+        assert_eq!(file.default_profile.as_deref(), Some("work"));
+        assert_eq!(file.profiles.len(), 2);
+        assert_eq!(file.profiles["other"].moco_company.as_deref(), Some("beta"));
+        assert!(file.profiles["work"].aliases.is_empty());
+    }
+
+    #[test]
+    fn upgrade_keeps_a_backup_of_the_original() {
+        let config = TempConfig::new("backup", Some(FLAT_V0));
+        read_config_file(&config.0).unwrap();
+
+        let backup = sibling(&config.0, ".v0.bak");
+        assert_eq!(read_to_string(backup).unwrap(), FLAT_V0);
+    }
+
+    #[test]
+    fn current_file_is_not_rewritten() {
+        let contents =
+            format!(r#"{{"version":{CONFIG_VERSION},"default_profile":null,"profiles":{{}}}}"#);
+        let config = TempConfig::new("current", Some(&contents));
+        read_config_file(&config.0).unwrap();
+
+        assert_eq!(read_to_string(&config.0).unwrap(), contents);
+        assert!(!sibling(&config.0, &format!(".v{CONFIG_VERSION}.bak")).exists());
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let newer = u64::from(CONFIG_VERSION) + 1;
+        let contents = format!(r#"{{"version":{newer},"profiles":{{}}}}"#);
+        let config = TempConfig::new("newer", Some(&contents));
+        let Err(error) = read_config_file(&config.0) else {
+            panic!("the file was accepted");
+        };
+
+        assert!(matches!(
+            error.downcast_ref::<ConfigError>(),
+            Some(ConfigError::UnsupportedVersion(_, version)) if *version == newer
+        ));
+        assert_eq!(read_to_string(&config.0).unwrap(), contents);
+    }
+
+    #[test]
+    fn ticket_url_escapes_the_ticket() {
+        let service = TicketService {
+            service: "gitlab".to_string(),
+            url: "https://git.example.com/issues?id={ticket}".to_string(),
+        };
+        assert_eq!(
+            service.ticket_url("AB-1"),
+            "https://git.example.com/issues?id=AB-1"
+        );
+        assert_eq!(
+            service.ticket_url("a b/c#1"),
+            "https://git.example.com/issues?id=a%20b%2Fc%231"
+        );
+    }
+
+    #[test]
+    fn unknown_key_is_reported() {
+        let config = TempConfig::new("unknown", Some(r#"{"moco_compnay":"acme"}"#));
+        let Err(error) = read_config_file(&config.0) else {
+            panic!("the file was accepted");
+        };
+
+        assert!(matches!(
+            error.downcast_ref::<ConfigError>(),
+            Some(ConfigError::InvalidFile(..))
+        ));
+        assert!(error.to_string().contains("moco_compnay"));
+    }
+
+    #[test]
+    fn schema_version_of_unversioned_files() {
+        assert_eq!(schema_version(&serde_json::from_str(FLAT_V0).unwrap()), 0);
+        assert_eq!(schema_version(&serde_json::json!({ "profiles": {} })), 1);
+        assert_eq!(schema_version(&serde_json::json!({})), 1);
+        assert_eq!(
+            schema_version(&serde_json::json!({ "version": "x" })),
+            u64::from(CONFIG_VERSION)
+        );
+    }
+}
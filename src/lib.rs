@@ -0,0 +1,11 @@
+//! MOCO API client, data models and configuration loader.
+//!
+//! The `mococli` binary is a thin consumer of this library; other tools can use
+//! [`moco::client::MocoClient`] together with [`config::init`] the same way. Encrypted
+//! profiles are unlocked through a [`config::KeySource`] supplied by the caller.
+
+pub mod config;
+pub mod moco;
+mod secrets;
+
+pub type BoxedError = Box<dyn std::error::Error + Send + Sync>;
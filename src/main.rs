@@ -2,35 +2,48 @@ use chrono::{Datelike, Local, Month, NaiveDate};
 use num_traits::FromPrimitive;
 //noinspection RsUnresolvedPath
 use owo_colors::OwoColorize;
-use std::rc::Rc;
-use std::str::FromStr;
-use std::{cell::RefCell, io::Write, vec};
+use std::process::ExitCode;
+use std::{io::Write, vec};
 use unicode_ellipsis::truncate_str;
-use utils::{prompt_task_select, render_table};
+use utils::{LookupError, TicketLink, is_unauthorized, prompt_task_select, render_table};
 
-use crate::moco::model::{
-    ControlActivityTimer, CreateActivity, DeleteActivity, GetActivity, PerformanceReportMonthly,
-};
+use crate::agent::AgentKeySource;
 use crate::utils::{
-    ARROW, BoxedError, activity_delete_loop, activity_select, ask_question_mandatory,
-    ask_question_prefilled, footer, prompt_activity_select_today, prompt_from_to_date,
+    ARROW, BoxedError, activity_delete_loop, activity_select, ask_confirmation, ask_new_passphrase,
+    ask_question_mandatory, ask_question_prefilled, footer, prompt_activity_select_today,
+    prompt_current_user, prompt_from_to_date, read_key_argument, verify_credentials,
+};
+use crate::utils::{ask_question, mandatory_validator};
+use mococli::config;
+use mococli::config::{Alias, AppConfig};
+use mococli::moco::model::{
+    ControlActivityTimer, CreateActivity, DeleteActivity, GetActivity, PerformanceReportMonthly,
 };
-use crate::{
-    moco::{client::MocoClient, model::EditActivity},
-    utils::{ask_question, mandatory_validator},
+use mococli::moco::{
+    client::{MocoClient, MocoClientError, Paginated},
+    model::EditActivity,
 };
+use serde_json::Value;
 
+mod agent;
 mod cli;
-mod config;
-mod moco;
-
 mod utils;
 
 const FORMAT_DATE_DAY: &str = "%A %Y-%m-%d";
 const FORMAT_DATE_DAY_WEEK: &str = constcat::concat!(FORMAT_DATE_DAY, " (CW %V)");
 
 #[tokio::main]
-async fn main() -> Result<(), BoxedError> {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{} {error}", "Error:".red().bold());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run() -> Result<(), BoxedError> {
     let args = cli::init();
     let mut log_builder = env_logger::builder();
     log_builder.parse_default_env();
@@ -38,34 +51,133 @@ async fn main() -> Result<(), BoxedError> {
         log_builder.filter_level(log::LevelFilter::Trace);
     }
     log_builder.init();
-    let config = Rc::new(RefCell::new(config::init()?));
+    let mut config = match args.command {
+        cli::Commands::Agent => return agent::serve().await,
+        // Settings are shown and changed as stored, without any environment overrides.
+        cli::Commands::Config { command } => {
+            return config_command(
+                command,
+                config::load_stored(args.config, args.profile.as_deref())?,
+            );
+        }
+        // Only adding an alias talks to MOCO.
+        cli::Commands::Alias {
+            command: cli::AliasCommand::List | cli::AliasCommand::Rm { .. },
+        } => config::load(args.config, args.profile.as_deref())?,
+        // Logging in replaces the keys, so there is no need to unlock the old ones.
+        cli::Commands::Login { .. } => config::load(args.config, args.profile.as_deref())?,
+        _ => config::init(args.config, args.profile.as_deref(), &AgentKeySource)?,
+    };
+    if let Some(base_url) = args.base_url {
+        config.moco_base_url = Some(base_url);
+    }
     let moco_client = MocoClient::new(&config);
 
     match args.command {
-        cli::Commands::Login => {
-            println!("MOCO Login");
-
-            let moco_company =
-                ask_question_mandatory("Enter your company's name: ", &mandatory_validator)?;
-            let api_key =
-                ask_question_mandatory("Enter your personal API key: ", &mandatory_validator)?;
-            let bot_api_key =
-                ask_question_mandatory("Enter the MOCO Bot API key: ", &mandatory_validator)?;
-
-            {
-                let mut cfg = config.borrow_mut();
-                cfg.moco_company = Some(moco_company.to_lowercase());
-                cfg.moco_api_key = Some(api_key);
-                cfg.moco_bot_api_key = Some(bot_api_key);
+        cli::Commands::Login {
+            set_default,
+            company,
+            api_key,
+            bot_api_key,
+            user_id,
+        } => {
+            println!("MOCO Login (profile '{}')", config.profile);
+
+            let moco_company = match company {
+                Some(company) => company,
+                None => {
+                    ask_question_mandatory("Enter your company's name: ", &mandatory_validator)?
+                }
+            };
+            config.moco_company = Some(moco_company.to_lowercase());
+
+            let mut passphrase = None;
+            if api_key.is_some() || bot_api_key.is_some() {
+                config.moco_api_key = Some(match api_key {
+                    Some(api_key) => read_key_argument(api_key)?,
+                    None => ask_question_mandatory(
+                        "Enter your personal API key: ",
+                        &mandatory_validator,
+                    )?,
+                });
+                config.moco_bot_api_key = Some(match bot_api_key {
+                    Some(bot_api_key) => read_key_argument(bot_api_key)?,
+                    None => ask_question_mandatory(
+                        "Enter the MOCO Bot API key: ",
+                        &mandatory_validator,
+                    )?,
+                });
+                config.api_key_command = None;
+                config.bot_api_key_command = None;
+                config.secrets = None;
+            } else if ask_confirmation(
+                "Store credential helper commands (e.g. 'pass show moco') instead of the API keys?",
+            )? {
+                config.api_key_command = Some(ask_question_mandatory(
+                    "Enter the command printing your personal API key: ",
+                    &mandatory_validator,
+                )?);
+                config.bot_api_key_command = Some(ask_question_mandatory(
+                    "Enter the command printing the MOCO Bot API key: ",
+                    &mandatory_validator,
+                )?);
+                config.moco_api_key = None;
+                config.moco_bot_api_key = None;
+                config.secrets = None;
+            } else {
+                config.moco_api_key = Some(ask_question_mandatory(
+                    "Enter your personal API key: ",
+                    &mandatory_validator,
+                )?);
+                config.moco_bot_api_key = Some(ask_question_mandatory(
+                    "Enter the MOCO Bot API key: ",
+                    &mandatory_validator,
+                )?);
+                config.api_key_command = None;
+                config.bot_api_key_command = None;
+                config.secrets = None;
+                passphrase = ask_confirmation("Encrypt the API keys with a passphrase?")?
+                    .then(ask_new_passphrase)
+                    .transpose()?;
             }
 
-            let firstname = ask_question_mandatory("Enter firstname: ", &mandatory_validator)?;
-            let lastname = ask_question_mandatory("Enter lastname:  ", &mandatory_validator)?;
+            match user_id {
+                Some(user_id) => {
+                    config.moco_user_id = Some(user_id);
+                    config.moco_user_name = None;
+                }
+                None => match prompt_current_user(&MocoClient::new(&config)).await {
+                    Ok(user) => {
+                        config.moco_user_id = Some(user.id);
+                        config.moco_user_name =
+                            Some(user.full_name()).filter(|name| !name.trim().is_empty());
+                    }
+                    // A rejected key is reported by the verification below.
+                    Err(error) if is_unauthorized(error.as_ref()) => {
+                        log::debug!("Could not identify the user: {error}");
+                        config.moco_user_id = None;
+                        config.moco_user_name = None;
+                    }
+                    Err(error) => return Err(error),
+                },
+            }
+            verify_credentials(&MocoClient::new(&config)).await?;
+            if config.moco_user_id.is_none() {
+                return Err(Box::new(MocoClientError::UserNotFound));
+            }
 
-            config.borrow_mut().moco_user_id =
-                Some(moco_client.get_user_id(firstname, lastname).await?);
-            config.borrow_mut().write_config()?;
-            println!("🎉 Logged in 🎊")
+            if let Some(passphrase) = passphrase {
+                config.encrypt_api_keys(&passphrase, &AgentKeySource)?;
+            }
+            // --base-url and MOCO_BASE_URL only apply to this run, the profile keeps its own.
+            config.moco_base_url =
+                config::load_stored(Some(config.path.clone()), Some(&config.profile))?
+                    .moco_base_url;
+            config.write_config(set_default)?;
+            match &config.moco_user_name {
+                Some(name) => println!("🎉 Logged in as {name} 🎊"),
+                None => println!("🎉 Logged in 🎊"),
+            }
         }
         cli::Commands::List {
             day,
@@ -73,6 +185,7 @@ async fn main() -> Result<(), BoxedError> {
             month,
             backward,
             date,
+            tag,
         } => {
             print!("List activities for ");
             std::io::stdout().flush()?;
@@ -92,22 +205,26 @@ async fn main() -> Result<(), BoxedError> {
                 )
             };
 
-            let activities = moco_client.get_activities(from, to, None, None).await?;
+            let Paginated {
+                items: mut activities,
+                mut total,
+            } = moco_client.get_activities(from, to, None, None).await?;
+            if let Some(tag) = tag {
+                activities.retain(|activity| activity.tag.to_lowercase() == tag.to_lowercase());
+                total = activities.len();
+            }
 
             let mut list: Vec<Vec<String>> = activities
                 .iter()
                 .map(|activity| {
                     vec![
-                        activity.date.clone(),
-                        activity
-                            .date
-                            .parse::<NaiveDate>()
-                            .unwrap()
-                            .weekday()
-                            .to_string(),
+                        activity.date.to_string(),
+                        activity.date.weekday().to_string(),
                         activity.hours.to_string(),
                         truncate_str(&activity.project.name, 14).to_string(),
                         activity.task.name.clone(),
+                        activity.tag.clone(),
+                        activity.remote_id.clone().unwrap_or_default(),
                         truncate_str(&activity.description.clone().unwrap_or(String::new()), 112)
                             .to_string(),
                     ]
@@ -121,22 +238,49 @@ async fn main() -> Result<(), BoxedError> {
                     "Hours".to_string(),
                     "Project".to_string(),
                     "Task".to_string(),
+                    "Tag".to_string(),
+                    "Ticket".to_string(),
                     "Description".to_string(),
                 ],
             );
 
-            list.push(footer(false, &activities));
+            let mut footer = footer(false, &activities);
+            footer[0] = format!("{total} entries");
+            footer.extend(["".to_string(), "".to_string()]);
+            list.push(footer);
 
             render_table(list);
         }
         cli::Commands::New {
+            alias,
             project,
             task,
             hours,
             date,
             description,
+            ticket,
+            tag,
+            quiet,
         } => {
-            let (project, task) = prompt_task_select(&moco_client, project, task).await?;
+            let (project, task, description) = match alias {
+                Some(name) => {
+                    let alias = config
+                        .aliases
+                        .get(&name)
+                        .ok_or(LookupError::NotFound("alias", name))?;
+                    (
+                        project.or(Some(alias.project_id.to_string())),
+                        task.or(Some(alias.task_id.to_string())),
+                        description.or(alias.description.clone()),
+                    )
+                }
+                None => (project, task, description),
+            };
+            let (project, task) = prompt_task_select(&moco_client, project, task, None).await?;
+            let ticket_link = match ticket {
+                Some(ticket) => TicketLink::new(&config, project.id, &ticket)?,
+                None => TicketLink::default(),
+            };
 
             let date = if let Some(d) = date {
                 d
@@ -172,60 +316,165 @@ async fn main() -> Result<(), BoxedError> {
                 utils::read_line()?
             };
 
-            moco_client
+            let activity = moco_client
                 .create_activity(&CreateActivity {
-                    date: date.to_string(),
+                    date,
                     project_id: project.id,
                     task_id: task.id,
                     hours: Some(hours),
                     description,
+                    remote_service: ticket_link.service,
+                    remote_id: ticket_link.id,
+                    remote_url: ticket_link.url,
+                    tag,
                     ..Default::default()
                 })
                 .await?;
+
+            if quiet {
+                println!("{}", activity.id);
+            } else {
+                println!(
+                    "Created activity {} on {}: {} hours on {} / {}{}",
+                    activity.id.bold(),
+                    activity.date,
+                    activity.hours,
+                    activity.project.name,
+                    activity.task.name,
+                    if activity.timer_started_at.is_some() {
+                        " (timer running)"
+                    } else {
+                        ""
+                    }
+                );
+            }
         }
-        cli::Commands::Edit { activity, date } => {
-            let activity = match date {
-                Some(date) => {
+        cli::Commands::Edit {
+            activity,
+            date,
+            project,
+            task,
+            new_date,
+            hours,
+            description,
+            append_description,
+            tag,
+            ticket,
+        } => {
+            let activity = match (activity, date) {
+                (Some(activity_id), None) => {
+                    moco_client.get_activity(&GetActivity { activity_id }).await
+                }
+                (activity, Some(date)) => {
                     println!("Edit activities for {}", date.format(FORMAT_DATE_DAY_WEEK));
                     activity_select(&moco_client, activity, date, date).await
                 }
-                None => {
+                (None, None) => {
                     let (from, to) = prompt_from_to_date()?;
                     activity_select(&moco_client, activity, from, to).await
                 }
             }?;
 
-            let date = ask_question_prefilled("New date (YYYY-MM-DD): ", &activity.date, &|answer| {
-                Ok(answer.parse::<NaiveDate>()?)
-            })?;
+            // With any of these flags the edit is scripted: nothing else is asked for.
+            let scripted = project.is_some()
+                || task.is_some()
+                || new_date.is_some()
+                || hours.is_some()
+                || description.is_some()
+                || append_description.is_some()
+                || tag.is_some()
+                || ticket.is_some();
+
+            let (project_id, task_id) = if scripted && project.is_none() && task.is_none() {
+                (activity.project.id, activity.task.id)
+            } else {
+                // A task alone is looked up in the activity's current project.
+                let project = match (project, &task) {
+                    (None, Some(_)) => Some(activity.project.id.to_string()),
+                    (project, _) => project,
+                };
+                let (project, task) = prompt_task_select(
+                    &moco_client,
+                    project,
+                    task,
+                    Some((activity.project.id, activity.task.id)),
+                )
+                .await?;
+                (project.id, task.id)
+            };
 
-            let hours = ask_question_prefilled(
-                "New duration (hours): ",
-                &activity.hours.to_string(),
-                &|answer| {
-                    Ok(match answer.replacen(',', ".", 1) {
-                        s if s == "." => "".to_string(),
-                        s if s.starts_with('.') => format!("0{}", s),
-                        s => s,
-                    }
-                    .parse::<f64>()?)
-                },
-            )?;
+            let current_description = activity.description.clone().unwrap_or_default();
+            let (date, hours, description) = if scripted {
+                let description = match append_description {
+                    Some(text) if current_description.is_empty() => text,
+                    Some(text) => format!("{current_description} {text}"),
+                    None => description.unwrap_or(current_description),
+                };
+                (
+                    new_date.unwrap_or(activity.date),
+                    hours.unwrap_or(activity.hours),
+                    description,
+                )
+            } else {
+                let date = ask_question_prefilled(
+                    "New date (YYYY-MM-DD): ",
+                    &activity.date.to_string(),
+                    &|answer| Ok(answer.parse::<NaiveDate>()?),
+                )?;
+
+                let hours = ask_question_prefilled(
+                    "New duration (hours): ",
+                    &activity.hours.to_string(),
+                    &|answer| {
+                        Ok(match answer.replacen(',', ".", 1) {
+                            s if s == "." => "".to_string(),
+                            s if s.starts_with('.') => format!("0{}", s),
+                            s => s,
+                        }
+                        .parse::<f64>()?)
+                    },
+                )?;
 
-            let description = ask_question_prefilled(
-                "New description: ",
-                &activity.description.clone().unwrap_or_default(),
-                &|answer| Ok(answer.to_string()),
-            )?;
+                let description =
+                    ask_question_prefilled("New description: ", &current_description, &|answer| {
+                        Ok(answer.to_string())
+                    })?;
+                (date, hours, description)
+            };
+
+            let ticket_link = match ticket {
+                Some(ticket) => TicketLink::new(&config, project_id, &ticket)?,
+                // Only asked for where there is something to link to, or unlink.
+                None if !scripted
+                    && (activity.remote_id.is_some()
+                        || config.ticket_service(project_id).is_some()) =>
+                {
+                    let ticket = ask_question_prefilled(
+                        "New ticket: ",
+                        &activity.remote_id.clone().unwrap_or_default(),
+                        &|answer| Ok(answer.trim().to_string()),
+                    )?;
+                    if Some(&ticket) == activity.remote_id.as_ref() {
+                        TicketLink::moved(&config, &activity, project_id)?
+                    } else {
+                        TicketLink::new(&config, project_id, &ticket)?
+                    }
+                }
+                None => TicketLink::moved(&config, &activity, project_id)?,
+            };
 
             moco_client
                 .edit_activity(&EditActivity {
                     activity_id: activity.id,
-                    project_id: activity.project.id,
-                    task_id: activity.task.id,
-                    date: date.to_string(),
+                    project_id,
+                    task_id,
+                    date,
                     description,
                     hours: hours.to_string(),
+                    tag: tag.unwrap_or(activity.tag),
+                    remote_service: ticket_link.service,
+                    remote_id: ticket_link.id,
+                    remote_url: ticket_link.url,
                 })
                 .await?;
         }
@@ -268,10 +517,23 @@ async fn main() -> Result<(), BoxedError> {
             }
             cli::Timer::Stop => {
                 let now = Local::now().date_naive();
-                let activities = moco_client.get_activities(now, now, None, None).await?;
-                let activity = activities.iter().find(|a| !a.timer_started_at.is_null());
+                let activities = moco_client
+                    .get_activities(now, now, None, None)
+                    .await?
+                    .items;
+                let activity = activities.iter().find(|a| a.timer_started_at.is_some());
 
                 if let Some(a) = activity {
+                    if let Some(elapsed) = a.timer_elapsed() {
+                        println!(
+                            "Stopping timer after {}:{:02} on {} / {}",
+                            elapsed.num_hours(),
+                            elapsed.num_minutes() % 60,
+                            a.project.name,
+                            a.task.name
+                        );
+                    }
+
                     moco_client
                         .control_activity_timer(&ControlActivityTimer {
                             control: "stop".to_string(),
@@ -288,10 +550,170 @@ async fn main() -> Result<(), BoxedError> {
                 }
             }
         },
+        cli::Commands::Projects {
+            search,
+            customer,
+            include_inactive,
+        } => {
+            let projects = moco_client
+                .get_assigned_projects((!include_inactive).then_some(true))
+                .await?
+                .items;
+            let contains =
+                |text: &str, term: &str| text.to_lowercase().contains(&term.to_lowercase());
+
+            let mut list = vec![vec![
+                "Customer".to_string(),
+                "Identifier".to_string(),
+                "Project".to_string(),
+                "Project ID".to_string(),
+                "Billable".to_string(),
+                "Task ID".to_string(),
+                "Task".to_string(),
+            ]];
+            let (mut project_count, mut task_count) = (0, 0);
+            for project in &projects {
+                if customer
+                    .as_ref()
+                    .is_some_and(|customer| !contains(&project.customer.name, customer))
+                {
+                    continue;
+                }
+                let project_matches = search.as_ref().is_none_or(|search| {
+                    contains(&project.identifier, search) || contains(&project.name, search)
+                });
+                // A search matching only some tasks narrows the project down to those.
+                let tasks: Vec<_> = project
+                    .tasks
+                    .iter()
+                    .filter(|task| include_inactive || task.active)
+                    .filter(|task| {
+                        project_matches
+                            || search
+                                .as_ref()
+                                .is_some_and(|search| contains(&task.name, search))
+                    })
+                    .collect();
+                if !project_matches && tasks.is_empty() {
+                    continue;
+                }
+
+                project_count += 1;
+                task_count += tasks.len();
+                let mut name = project.name.clone();
+                if !project.active {
+                    name.push_str(" (inactive)");
+                }
+                let project_columns = vec![
+                    project.customer.name.clone(),
+                    project.identifier.clone(),
+                    name,
+                    project.id.to_string(),
+                    if project.billable { "yes" } else { "no" }.to_string(),
+                ];
+                if tasks.is_empty() {
+                    list.push(
+                        [
+                            project_columns.clone(),
+                            vec!["-".to_string(), "-".to_string()],
+                        ]
+                        .concat(),
+                    );
+                }
+                for task in tasks {
+                    let mut task_name = task.name.clone();
+                    if !task.active {
+                        task_name.push_str(" (inactive)");
+                    }
+                    list.push(
+                        [
+                            project_columns.clone(),
+                            vec![task.id.to_string(), task_name],
+                        ]
+                        .concat(),
+                    );
+                }
+            }
+            list.push(vec![
+                format!("{project_count} projects"),
+                ARROW.to_string(),
+                "".to_string(),
+                "".to_string(),
+                "".to_string(),
+                "".to_string(),
+                format!("{task_count} tasks"),
+            ]);
+
+            render_table(list);
+        }
+        cli::Commands::Alias { command } => match command {
+            cli::AliasCommand::Add {
+                name,
+                project,
+                task,
+                description,
+            } => {
+                let (project, task) =
+                    prompt_task_select(&moco_client, Some(project), Some(task), None).await?;
+                let alias = Alias {
+                    project_id: project.id,
+                    project_name: project.name,
+                    task_id: task.id,
+                    task_name: task.name,
+                    description,
+                };
+                println!(
+                    "Alias '{name}': {} / {}",
+                    alias.project_name, alias.task_name
+                );
+                config.update_stored(|stored| {
+                    stored.aliases.insert(name, alias);
+                    Ok(())
+                })?;
+            }
+            cli::AliasCommand::List => {
+                let mut list = vec![vec![
+                    "Alias".to_string(),
+                    "Project".to_string(),
+                    "Task".to_string(),
+                    "Description".to_string(),
+                ]];
+                for (name, alias) in &config.aliases {
+                    list.push(vec![
+                        name.clone(),
+                        format!("{} ({})", alias.project_name, alias.project_id),
+                        format!("{} ({})", alias.task_name, alias.task_id),
+                        alias.description.clone().unwrap_or_default(),
+                    ]);
+                }
+                render_table(list);
+            }
+            cli::AliasCommand::Rm { name } => {
+                if !config.aliases.contains_key(&name) {
+                    return Err(Box::new(LookupError::NotFound("alias", name)));
+                }
+                config.update_stored(|stored| {
+                    stored.aliases.remove(&name);
+                    Ok(())
+                })?;
+            }
+        },
+        cli::Commands::Agent | cli::Commands::Config { .. } => {
+            unreachable!("handled before the config is loaded")
+        }
         cli::Commands::Overtime { monthly } => {
-            let overtime = moco_client.get_user_performance_report().await?;
+            // The monthly breakdown needs both reports, so fetch them side by side.
+            let (overtime, work_time_adjustments) = if monthly {
+                let (overtime, adjustments) = tokio::try_join!(
+                    moco_client.get_user_performance_report(),
+                    moco_client.get_user_work_time_adjustments()
+                )?;
+                (overtime, Some(adjustments))
+            } else {
+                (moco_client.get_user_performance_report().await?, None)
+            };
 
-            if monthly {
+            if let Some(work_time_adjustments) = work_time_adjustments {
                 let now = Local::now();
 
                 let monthly_reports: Vec<&PerformanceReportMonthly> = overtime
@@ -300,13 +722,9 @@ async fn main() -> Result<(), BoxedError> {
                     .filter(|report| report.month <= now.month())
                     .collect();
 
-                let work_time_adjustments = moco_client
-                    .get_user_work_time_adjustments()
-                    .await?
+                let work_time_adjustments = work_time_adjustments
                     .iter()
-                    .filter(|adjustment| {
-                        NaiveDate::from_str(&adjustment.date).unwrap().year() == now.year()
-                    })
+                    .filter(|adjustment| adjustment.date.year() == now.year())
                     .map(|a| a.hours)
                     .sum::<f64>();
 
@@ -371,3 +789,66 @@ async fn main() -> Result<(), BoxedError> {
 
     Ok(())
 }
+
+fn config_command(command: cli::ConfigCommand, config: AppConfig) -> Result<(), BoxedError> {
+    match command {
+        cli::ConfigCommand::Show => {
+            println!(
+                "Profile '{}' in {}\n",
+                config.profile,
+                config.path.display()
+            );
+            let mut list = vec![vec!["Key".to_string(), "Value".to_string()]];
+            for (key, value) in config.to_map()? {
+                let encrypted = config.secrets.is_some()
+                    && (key == "secrets" || config::ENCRYPTED_KEYS.contains(&key.as_str()));
+                let value = match value {
+                    _ if encrypted => "(encrypted)".to_string(),
+                    Value::Null => "-".to_string(),
+                    Value::String(secret) if config::ENCRYPTED_KEYS.contains(&key.as_str()) => {
+                        mask_secret(&secret)
+                    }
+                    Value::String(value) => value,
+                    value => value.to_string(),
+                };
+                list.push(vec![key, value]);
+            }
+            render_table(list);
+        }
+        cli::ConfigCommand::Get { key } => match config.get(&key, &AgentKeySource)? {
+            Value::Null => {}
+            Value::String(value) => println!("{value}"),
+            value => println!("{value}"),
+        },
+        cli::ConfigCommand::Set { key, value } => {
+            config.update_stored(|stored| stored.set(&key, Some(&value), &AgentKeySource))?;
+        }
+        cli::ConfigCommand::Unset { key } => {
+            config.update_stored(|stored| stored.set(&key, None, &AgentKeySource))?;
+        }
+        cli::ConfigCommand::Path => println!("{}", config.path.display()),
+        cli::ConfigCommand::Validate => {
+            let problems = config.validate();
+            if !problems.is_empty() {
+                for problem in &problems {
+                    println!("{} {problem}", "✘".red());
+                }
+                return Err(format!("Profile '{}' is incomplete", config.profile).into());
+            }
+            println!("{} Profile '{}' is complete", "✔".green(), config.profile);
+        }
+    }
+    Ok(())
+}
+
+/// Keeps only the last four characters of an API key, enough to tell keys apart.
+/// Short values are masked completely.
+fn mask_secret(secret: &str) -> String {
+    let length = secret.chars().count();
+    let visible = if length > 8 { length - 4 } else { length };
+    secret
+        .chars()
+        .enumerate()
+        .map(|(index, char)| if index < visible { '*' } else { char })
+        .collect()
+}
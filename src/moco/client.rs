@@ -1,82 +1,405 @@
 use crate::moco::model::{
     Activity, ControlActivityTimer, CreateActivity, DeleteActivity, EditActivity, Employment,
-    GetActivity, PerformanceReport, Projects, WorkTimeAdjustment,
+    GetActivity, PerformanceReport, Project, Session, WorkTimeAdjustment,
 };
-use chrono::{Local, NaiveDate};
-use reqwest::Client;
-use std::rc::Rc;
-use std::{cell::RefCell, error::Error};
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use reqwest::header::{HeaderMap, LINK, RETRY_AFTER};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::sync::OnceCell;
 
+use crate::BoxedError;
 use crate::config::AppConfig;
-use crate::utils::BoxedError;
 
-pub(crate) struct MocoClient {
+/// Client for the MOCO REST API of a single workspace.
+///
+/// Cloning is cheap and clones share the connection pool, so a client can be
+/// handed to spawned tasks to issue requests concurrently.
+#[derive(Debug, Clone)]
+pub struct MocoClient {
     client: Client,
-    config: Rc<RefCell<AppConfig>>,
+    credentials: Arc<Credentials>,
+    retry: RetryPolicy,
+}
+
+/// Everything needed to talk to MOCO, resolved once from an [`AppConfig`].
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    pub base_url: Option<String>,
+    pub api_key: ApiKey,
+    pub bot_api_key: ApiKey,
+    pub user_id: Option<i64>,
+}
+
+impl From<&AppConfig> for Credentials {
+    fn from(config: &AppConfig) -> Self {
+        Credentials {
+            base_url: config.base_url(),
+            api_key: ApiKey::new(config.moco_api_key.clone(), config.api_key_command.clone()),
+            bot_api_key: ApiKey::new(
+                config.moco_bot_api_key.clone(),
+                config.bot_api_key_command.clone(),
+            ),
+            user_id: config.moco_user_id,
+        }
+    }
+}
+
+/// An API key given literally or printed by a credential helper command such as
+/// `pass show moco`. The command only runs the first time the key is needed.
+#[derive(Debug, Clone, Default)]
+pub struct ApiKey {
+    value: Option<String>,
+    command: Option<String>,
+    resolved: OnceCell<Option<String>>,
+}
+
+impl ApiKey {
+    /// A literal `value` takes precedence over the `command`.
+    pub fn new(value: Option<String>, command: Option<String>) -> Self {
+        ApiKey {
+            value,
+            command,
+            resolved: OnceCell::new(),
+        }
+    }
+
+    pub async fn get(&self) -> Result<Option<&str>, BoxedError> {
+        let resolved = self
+            .resolved
+            .get_or_try_init(|| async {
+                match (&self.value, &self.command) {
+                    (Some(value), _) => Ok(Some(value.clone())),
+                    (None, Some(command)) => run_credential_command(command).await.map(Some),
+                    (None, None) => Ok(None),
+                }
+            })
+            .await?;
+        Ok(resolved.as_deref())
+    }
+}
+
+/// Runs `command` through the platform shell and returns the first line it prints.
+async fn run_credential_command(command: &str) -> Result<String, BoxedError> {
+    let output = if cfg!(windows) {
+        Command::new("cmd").args(["/C", command]).output().await?
+    } else {
+        Command::new("sh").args(["-c", command]).output().await?
+    };
+
+    if !output.status.success() {
+        return Err(Box::new(MocoClientError::CredentialCommandFailed(
+            command.to_string(),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )));
+    }
+    let stdout = String::from_utf8(output.stdout)?;
+    match stdout.lines().next().map(str::trim) {
+        Some(secret) if !secret.is_empty() => Ok(secret.to_string()),
+        _ => Err(Box::new(MocoClientError::CredentialCommandFailed(
+            command.to_string(),
+            "no output".to_string(),
+        ))),
+    }
 }
 
 #[derive(Debug, derive_more::Display)]
-enum MocoClientError {
+pub enum MocoClientError {
+    #[display("Not logged in - run `mococli login` first")]
     NotLoggedIn,
+    #[display("No MOCO user found to log in as")]
     UserNotFound,
+    #[display("Credential command `{_0}` failed: {_1}")]
+    CredentialCommandFailed(String, String),
 }
 impl Error for MocoClientError {}
 
-#[allow(clippy::await_holding_refcell_ref)]
+/// A non-success response of the MOCO API, displayed as e.g. `422: hours must be positive`.
+#[derive(Debug, derive_more::Display)]
+#[display("{}: {message}", status.as_u16())]
+pub struct MocoApiError {
+    pub status: StatusCode,
+    pub message: String,
+    pub endpoint: String,
+}
+impl Error for MocoApiError {}
+
+/// All items of a paginated list endpoint together with the `X-Total` count MOCO reported.
+#[derive(Debug, Clone)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub total: usize,
+}
+
+/// Extracts the `rel="next"` target of a `Link` header, e.g.
+/// `<https://x.mocoapp.com/api/v1/activities?page=2>; rel="next"`.
+fn next_page_url(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(LINK)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .find_map(|link| {
+            let (url, params) = link.split_once(';')?;
+            params
+                .split(';')
+                .any(|param| param.trim() == r#"rel="next""#)
+                .then(|| {
+                    url.trim()
+                        .trim_start_matches('<')
+                        .trim_end_matches('>')
+                        .to_string()
+                })
+        })
+}
+
+/// How often and how patiently rate-limited (429) and failing (5xx) requests are repeated.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    const DEFAULT_MAX_RETRIES: u32 = 3;
+    const DEFAULT_BASE_DELAY_MS: u64 = 500;
+    const DEFAULT_MAX_DELAY_MS: u64 = 60_000;
+
+    fn from_config(config: &AppConfig) -> Self {
+        RetryPolicy {
+            max_retries: config
+                .retry_max_retries
+                .unwrap_or(Self::DEFAULT_MAX_RETRIES),
+            base_delay: Duration::from_millis(
+                config
+                    .retry_base_delay_ms
+                    .unwrap_or(Self::DEFAULT_BASE_DELAY_MS),
+            ),
+            max_delay: Duration::from_millis(
+                config
+                    .retry_max_delay_ms
+                    .unwrap_or(Self::DEFAULT_MAX_DELAY_MS),
+            ),
+        }
+    }
+
+    /// Exponential backoff: `base_delay`, `2 * base_delay`, `4 * base_delay`, ...
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.base_delay.saturating_mul(2u32.saturating_pow(attempt))
+    }
+
+    /// The wait before retry `attempt + 1`: the `requested` delay, else the backoff, but
+    /// never longer than `max_delay`.
+    fn delay(&self, attempt: u32, requested: Option<Duration>) -> Duration {
+        requested
+            .unwrap_or_else(|| self.backoff(attempt))
+            .min(self.max_delay)
+    }
+}
+
+/// `X-RateLimit-Reset` values from here on are epoch timestamps (September 2001), smaller
+/// ones are seconds.
+const EPOCH_THRESHOLD: u64 = 1_000_000_000;
+
+/// Reads how long MOCO asks us to wait, either from `Retry-After` (seconds or HTTP date)
+/// or from `X-RateLimit-Reset`. The latter is taken as an epoch timestamp when it is too
+/// large to be a number of seconds, so a reset that already passed means no wait.
+fn retry_after(headers: &HeaderMap, now: DateTime<Utc>) -> Option<Duration> {
+    let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+
+    header(RETRY_AFTER.as_str())
+        .and_then(|value| {
+            value
+                .trim()
+                .parse::<u64>()
+                .map(Duration::from_secs)
+                .ok()
+                .or_else(|| {
+                    let at = DateTime::parse_from_rfc2822(value)
+                        .ok()?
+                        .with_timezone(&Utc);
+                    (at - now).to_std().ok()
+                })
+        })
+        .or_else(|| {
+            let reset = header("X-RateLimit-Reset")?.trim().parse::<u64>().ok()?;
+            let now = u64::try_from(now.timestamp()).unwrap_or_default();
+            Some(Duration::from_secs(if reset >= EPOCH_THRESHOLD {
+                reset.saturating_sub(now)
+            } else {
+                reset
+            }))
+        })
+}
+
+trait SendChecked {
+    /// Sends the request, retries it according to `retry` and turns every remaining
+    /// non-2xx response into a [`MocoApiError`].
+    async fn send_checked(self, retry: RetryPolicy) -> Result<Response, BoxedError>;
+}
+
+impl SendChecked for RequestBuilder {
+    async fn send_checked(self, retry: RetryPolicy) -> Result<Response, BoxedError> {
+        let (client, request) = self.build_split();
+        let request = request?;
+        // POST and PATCH may already have taken effect when MOCO fails with a 5xx.
+        let idempotent = [Method::GET, Method::PUT, Method::DELETE].contains(request.method());
+
+        let mut attempt = 0;
+        let response = loop {
+            let Some(attempt_request) = request.try_clone().filter(|_| attempt < retry.max_retries)
+            else {
+                break client.execute(request).await?;
+            };
+
+            let response = client.execute(attempt_request).await?;
+            let status = response.status();
+            let delay = if status == StatusCode::TOO_MANY_REQUESTS {
+                retry.delay(attempt, retry_after(response.headers(), Utc::now()))
+            } else if status.is_server_error() && idempotent {
+                retry.delay(attempt, None)
+            } else {
+                break response;
+            };
+
+            attempt += 1;
+            log::debug!(
+                "{} responded with {status}, retry {attempt}/{} in {delay:?}",
+                response.url().path(),
+                retry.max_retries
+            );
+            tokio::time::sleep(delay).await;
+        };
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let endpoint = response.url().path().to_string();
+        let body = response.text().await.unwrap_or_default();
+        // MOCO answers errors with `{"message": "..."}`, anything else is shown verbatim.
+        let message = serde_json::from_str::<Value>(&body)
+            .ok()
+            .and_then(|json| json.get("message")?.as_str().map(str::to_string))
+            .or_else(|| Some(body.trim().to_string()).filter(|body| !body.is_empty()))
+            .or_else(|| status.canonical_reason().map(str::to_string))
+            .unwrap_or_default();
+
+        log::debug!("{endpoint} responded with {status}: {body}");
+        Err(Box::new(MocoApiError {
+            status,
+            message,
+            endpoint,
+        }))
+    }
+}
+
 impl MocoClient {
-    pub(crate) fn new(app_config: &Rc<RefCell<AppConfig>>) -> Self {
+    pub fn new(app_config: &AppConfig) -> Self {
         MocoClient {
             client: Client::new(),
-            config: app_config.clone(),
+            credentials: Arc::new(Credentials::from(app_config)),
+            retry: RetryPolicy::from_config(app_config),
         }
     }
 
-    pub(crate) async fn get_user_id(
+    /// Follows the `Link` headers of a list endpoint until the last page is read.
+    async fn get_all_pages<T: DeserializeOwned>(
         &self,
-        firstname: String,
-        lastname: String,
-    ) -> Result<i64, BoxedError> {
-        let config = &self.config.borrow();
-        match (&config.moco_api_key, &config.moco_company) {
-            (Some(api_key), Some(company)) => {
-                let employments = self
-                    .client
-                    .get(format!(
-                        "https://{company}.mocoapp.com/api/v1/users/employments?from={}",
-                        Local::now().date_naive()
-                    ))
-                    .header("Authorization", format!("Token token={}", api_key))
-                    .send()
-                    .await?
-                    .json::<Vec<Employment>>()
-                    .await?;
-                Ok(employments
-                    .iter()
-                    .find(|employment| {
-                        employment.user.firstname.to_lowercase() == firstname.to_lowercase()
-                            && employment.user.lastname.to_lowercase() == lastname.to_lowercase()
-                    })
-                    .ok_or(Box::new(MocoClientError::UserNotFound))?
-                    .user
-                    .id)
+        request: RequestBuilder,
+        api_key: &str,
+    ) -> Result<Paginated<T>, BoxedError> {
+        let mut request = request;
+        let mut items = Vec::new();
+        let mut total = None;
+        loop {
+            let response = request.send_checked(self.retry).await?;
+            total = total.or_else(|| {
+                response
+                    .headers()
+                    .get("X-Total")
+                    .and_then(|value| value.to_str().ok()?.parse::<usize>().ok())
+            });
+            let next = next_page_url(response.headers());
+            items.extend(response.json::<Vec<T>>().await?);
+
+            match next {
+                Some(url) => {
+                    request = self
+                        .client
+                        .get(url)
+                        .header("Authorization", format!("Token token={}", api_key))
+                }
+                None => break,
             }
+        }
+
+        let total = total.unwrap_or(items.len());
+        if total != items.len() {
+            log::warn!("MOCO reported {total} items but {} were read", items.len());
+        }
+        Ok(Paginated { items, total })
+    }
+
+    /// The user the personal API key belongs to.
+    pub async fn get_session(&self) -> Result<Session, BoxedError> {
+        let credentials = &self.credentials;
+        match (credentials.api_key.get().await?, &credentials.base_url) {
+            (Some(api_key), Some(base_url)) => Ok(self
+                .client
+                .get(format!("{base_url}/session"))
+                .header("Authorization", format!("Token token={}", api_key))
+                .send_checked(self.retry)
+                .await?
+                .json::<Session>()
+                .await?),
+            (_, _) => Err(Box::new(MocoClientError::NotLoggedIn)),
+        }
+    }
+
+    /// The employments of all users that are employed today.
+    pub async fn get_employments(&self) -> Result<Vec<Employment>, BoxedError> {
+        let credentials = &self.credentials;
+        match (credentials.api_key.get().await?, &credentials.base_url) {
+            (Some(api_key), Some(base_url)) => Ok(self
+                .get_all_pages::<Employment>(
+                    self.client
+                        .get(format!(
+                            "{base_url}/users/employments?from={}",
+                            Local::now().date_naive()
+                        ))
+                        .header("Authorization", format!("Token token={}", api_key)),
+                    api_key,
+                )
+                .await?
+                .items),
             (_, _) => Err(Box::new(MocoClientError::NotLoggedIn)),
         }
     }
 
-    pub(crate) async fn get_activities(
+    pub async fn get_activities(
         &self,
         from: NaiveDate,
         to: NaiveDate,
         task_id: Option<String>,
         term: Option<String>,
-    ) -> Result<Vec<Activity>, BoxedError> {
+    ) -> Result<Paginated<Activity>, BoxedError> {
         let mut parameter = vec![
             ("from", from.to_string()),
             ("to", to.to_string()),
             (
                 "user_id",
-                format!("{}", &self.config.borrow().moco_user_id.unwrap()),
+                self.credentials
+                    .user_id
+                    .ok_or(MocoClientError::NotLoggedIn)?
+                    .to_string(),
             ),
         ];
 
@@ -87,32 +410,30 @@ impl MocoClient {
             parameter.push(("term", x))
         }
 
-        let config = &self.config.borrow();
-        match (&config.moco_api_key, &config.moco_company) {
-            (Some(api_key), Some(company)) => Ok(self
-                .client
-                .get(format!("https://{company}.mocoapp.com/api/v1/activities"))
-                .query(&parameter)
-                .header("Authorization", format!("Token token={}", api_key))
-                .send()
-                .await?
-                .json::<Vec<Activity>>()
-                .await?),
+        let credentials = &self.credentials;
+        match (credentials.api_key.get().await?, &credentials.base_url) {
+            (Some(api_key), Some(base_url)) => {
+                self.get_all_pages(
+                    self.client
+                        .get(format!("{base_url}/activities"))
+                        .query(&parameter)
+                        .header("Authorization", format!("Token token={}", api_key)),
+                    api_key,
+                )
+                .await
+            }
             (_, _) => Err(Box::new(MocoClientError::NotLoggedIn)),
         }
     }
 
-    pub(crate) async fn get_activity(&self, payload: &GetActivity) -> Result<Activity, BoxedError> {
-        let config = &self.config.borrow();
-        match (&config.moco_api_key, &config.moco_company) {
-            (Some(api_key), Some(company)) => Ok(self
+    pub async fn get_activity(&self, payload: &GetActivity) -> Result<Activity, BoxedError> {
+        let credentials = &self.credentials;
+        match (credentials.api_key.get().await?, &credentials.base_url) {
+            (Some(api_key), Some(base_url)) => Ok(self
                 .client
-                .get(format!(
-                    "https://{company}.mocoapp.com/api/v1/activities/{}",
-                    payload.activity_id
-                ))
+                .get(format!("{base_url}/activities/{}", payload.activity_id))
                 .header("Authorization", format!("Token token={}", api_key))
-                .send()
+                .send_checked(self.retry)
                 .await?
                 .json::<Activity>()
                 .await?),
@@ -120,34 +441,31 @@ impl MocoClient {
         }
     }
 
-    pub(crate) async fn create_activity(&self, payload: &CreateActivity) -> Result<(), BoxedError> {
-        let config = &self.config.borrow();
-        match (&config.moco_api_key, &config.moco_company) {
-            (Some(api_key), Some(company)) => {
-                self.client
-                    .post(format!("https://{company}.mocoapp.com/api/v1/activities"))
-                    .header("Authorization", format!("Token token={}", api_key))
-                    .json(payload)
-                    .send()
-                    .await?;
-                Ok(())
-            }
+    pub async fn create_activity(&self, payload: &CreateActivity) -> Result<Activity, BoxedError> {
+        let credentials = &self.credentials;
+        match (credentials.api_key.get().await?, &credentials.base_url) {
+            (Some(api_key), Some(base_url)) => Ok(self
+                .client
+                .post(format!("{base_url}/activities"))
+                .header("Authorization", format!("Token token={}", api_key))
+                .json(payload)
+                .send_checked(self.retry)
+                .await?
+                .json::<Activity>()
+                .await?),
             (_, _) => Err(Box::new(MocoClientError::NotLoggedIn)),
         }
     }
 
-    pub(crate) async fn edit_activity(&self, payload: &EditActivity) -> Result<(), BoxedError> {
-        let config = &self.config.borrow();
-        match (&config.moco_api_key, &config.moco_company) {
-            (Some(api_key), Some(company)) => {
+    pub async fn edit_activity(&self, payload: &EditActivity) -> Result<(), BoxedError> {
+        let credentials = &self.credentials;
+        match (credentials.api_key.get().await?, &credentials.base_url) {
+            (Some(api_key), Some(base_url)) => {
                 self.client
-                    .put(format!(
-                        "https://{company}.mocoapp.com/api/v1/activities/{}",
-                        payload.activity_id
-                    ))
+                    .put(format!("{base_url}/activities/{}", payload.activity_id))
                     .header("Authorization", format!("Token token={}", api_key))
                     .json(payload)
-                    .send()
+                    .send_checked(self.retry)
                     .await?;
                 Ok(())
             }
@@ -155,17 +473,14 @@ impl MocoClient {
         }
     }
 
-    pub(crate) async fn delete_activity(&self, payload: &DeleteActivity) -> Result<(), BoxedError> {
-        let config = &self.config.borrow();
-        match (&config.moco_api_key, &config.moco_company) {
-            (Some(api_key), Some(company)) => {
+    pub async fn delete_activity(&self, payload: &DeleteActivity) -> Result<(), BoxedError> {
+        let credentials = &self.credentials;
+        match (credentials.api_key.get().await?, &credentials.base_url) {
+            (Some(api_key), Some(base_url)) => {
                 self.client
-                    .delete(format!(
-                        "https://{company}.mocoapp.com/api/v1/activities/{}",
-                        payload.activity_id
-                    ))
+                    .delete(format!("{base_url}/activities/{}", payload.activity_id))
                     .header("Authorization", format!("Token token={}", api_key))
-                    .send()
+                    .send_checked(self.retry)
                     .await?;
                 Ok(())
             }
@@ -173,20 +488,20 @@ impl MocoClient {
         }
     }
 
-    pub(crate) async fn control_activity_timer(
+    pub async fn control_activity_timer(
         &self,
         payload: &ControlActivityTimer,
     ) -> Result<(), BoxedError> {
-        let config = &self.config.borrow();
-        match (&config.moco_api_key, &config.moco_company) {
-            (Some(api_key), Some(company)) => {
+        let credentials = &self.credentials;
+        match (credentials.api_key.get().await?, &credentials.base_url) {
+            (Some(api_key), Some(base_url)) => {
                 self.client
                     .patch(format!(
-                        "https://{company}.mocoapp.com/api/v1/activities/{}/{}_timer",
+                        "{base_url}/activities/{}/{}_timer",
                         payload.activity_id, payload.control
                     ))
                     .header("Authorization", format!("Token token={}", api_key))
-                    .send()
+                    .send_checked(self.retry)
                     .await?;
                 Ok(())
             }
@@ -194,39 +509,45 @@ impl MocoClient {
         }
     }
 
-    pub(crate) async fn get_assigned_projects(&self) -> Result<Projects, BoxedError> {
-        let config = &self.config.borrow();
-        match (&config.moco_api_key, &config.moco_company) {
-            (Some(api_key), Some(company)) => Ok(self
-                .client
-                .get(format!(
-                    "https://{company}.mocoapp.com/api/v1/projects/assigned?active=true"
-                ))
-                .header("Authorization", format!("Token token={}", api_key))
-                .send()
-                .await?
-                .json::<Projects>()
-                .await?),
+    /// The projects the user is assigned to, only active or inactive ones as given by
+    /// `active`, or all of them for `None`.
+    pub async fn get_assigned_projects(
+        &self,
+        active: Option<bool>,
+    ) -> Result<Paginated<Project>, BoxedError> {
+        let mut parameter = vec![];
+        if let Some(x) = active {
+            parameter.push(("active", x.to_string()))
+        }
+
+        let credentials = &self.credentials;
+        match (credentials.api_key.get().await?, &credentials.base_url) {
+            (Some(api_key), Some(base_url)) => {
+                self.get_all_pages(
+                    self.client
+                        .get(format!("{base_url}/projects/assigned"))
+                        .query(&parameter)
+                        .header("Authorization", format!("Token token={}", api_key)),
+                    api_key,
+                )
+                .await
+            }
             (_, _) => Err(Box::new(MocoClientError::NotLoggedIn)),
         }
     }
 
-    pub(crate) async fn get_user_performance_report(
-        &self,
-    ) -> Result<PerformanceReport, BoxedError> {
-        let config = &self.config.borrow();
+    pub async fn get_user_performance_report(&self) -> Result<PerformanceReport, BoxedError> {
+        let credentials = &self.credentials;
         match (
-            &config.moco_bot_api_key,
-            &config.moco_company,
-            &config.moco_user_id,
+            credentials.bot_api_key.get().await?,
+            &credentials.base_url,
+            &credentials.user_id,
         ) {
-            (Some(bot_api_key), Some(company), Some(user_id)) => Ok(self
+            (Some(bot_api_key), Some(base_url), Some(user_id)) => Ok(self
                 .client
-                .get(format!(
-                    "https://{company}.mocoapp.com/api/v1/users/{user_id}/performance_report"
-                ))
+                .get(format!("{base_url}/users/{user_id}/performance_report"))
                 .header("Authorization", format!("Token token={}", bot_api_key))
-                .send()
+                .send_checked(self.retry)
                 .await?
                 .json::<PerformanceReport>()
                 .await?),
@@ -234,26 +555,139 @@ impl MocoClient {
         }
     }
 
-    pub(crate) async fn get_user_work_time_adjustments(
+    pub async fn get_user_work_time_adjustments(
         &self,
     ) -> Result<Vec<WorkTimeAdjustment>, BoxedError> {
-        let config = &self.config.borrow();
+        let credentials = &self.credentials;
         match (
-            &config.moco_bot_api_key,
-            &config.moco_company,
-            &config.moco_user_id,
+            credentials.bot_api_key.get().await?,
+            &credentials.base_url,
+            &credentials.user_id,
         ) {
-            (Some(bot_api_key), Some(company), Some(user_id)) => Ok(self
-                .client
-                .get(format!(
-                    "https://{company}.mocoapp.com/api/v1/users/work_time_adjustments?user_id={user_id}"
-                ))
-                .header("Authorization", format!("Token token={}", bot_api_key))
-                .send()
+            (Some(bot_api_key), Some(base_url), Some(user_id)) => Ok(self
+                .get_all_pages(
+                    self.client
+                        .get(format!(
+                            "{base_url}/users/work_time_adjustments?user_id={user_id}"
+                        ))
+                        .header("Authorization", format!("Token token={}", bot_api_key)),
+                    bot_api_key,
+                )
                 .await?
-                .json::<Vec<WorkTimeAdjustment>>()
-                .await?),
+                .items),
             (_, _, _) => Err(Box::new(MocoClientError::NotLoggedIn)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn headers(entries: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in entries {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2024-01-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn next_page_url_finds_the_next_link() {
+        let headers = headers(&[(
+            "link",
+            r#"<https://x.mocoapp.com/api/v1/activities?page=1>; rel="first", <https://x.mocoapp.com/api/v1/activities?page=3>; rel="next", <https://x.mocoapp.com/api/v1/activities?page=9>; rel="last""#,
+        )]);
+        assert_eq!(
+            next_page_url(&headers).as_deref(),
+            Some("https://x.mocoapp.com/api/v1/activities?page=3")
+        );
+    }
+
+    #[test]
+    fn next_page_url_reads_every_link_header() {
+        let headers = headers(&[
+            ("link", r#"<https://x/a?page=1>; rel="prev""#),
+            ("link", r#"<https://x/a?page=3>; rel="next""#),
+        ]);
+        assert_eq!(
+            next_page_url(&headers).as_deref(),
+            Some("https://x/a?page=3")
+        );
+    }
+
+    #[test]
+    fn next_page_url_is_none_on_the_last_page() {
+        assert_eq!(next_page_url(&HeaderMap::new()), None);
+        let headers = headers(&[("link", r#"<https://x/a?page=1>; rel="first""#)]);
+        assert_eq!(next_page_url(&headers), None);
+    }
+
+    #[test]
+    fn retry_after_reads_seconds_and_http_dates() {
+        let seconds = headers(&[("retry-after", "7")]);
+        assert_eq!(retry_after(&seconds, now()), Some(Duration::from_secs(7)));
+
+        let date = headers(&[("retry-after", "Mon, 01 Jan 2024 12:00:30 +0000")]);
+        assert_eq!(retry_after(&date, now()), Some(Duration::from_secs(30)));
+
+        assert_eq!(retry_after(&HeaderMap::new(), now()), None);
+    }
+
+    #[test]
+    fn retry_after_reads_rate_limit_reset_as_seconds_or_epoch() {
+        let seconds = headers(&[("x-ratelimit-reset", "12")]);
+        assert_eq!(retry_after(&seconds, now()), Some(Duration::from_secs(12)));
+
+        let epoch = now().timestamp() + 20;
+        let epoch_headers = {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-ratelimit-reset", HeaderValue::from(epoch));
+            headers
+        };
+        assert_eq!(
+            retry_after(&epoch_headers, now()),
+            Some(Duration::from_secs(20))
+        );
+
+        for passed in [now().timestamp(), now().timestamp() - 5] {
+            let mut passed_headers = HeaderMap::new();
+            passed_headers.insert("x-ratelimit-reset", HeaderValue::from(passed));
+            assert_eq!(retry_after(&passed_headers, now()), Some(Duration::ZERO));
+        }
+    }
+
+    #[test]
+    fn retry_after_prefers_retry_after() {
+        let headers = headers(&[("retry-after", "3"), ("x-ratelimit-reset", "50")]);
+        assert_eq!(retry_after(&headers, now()), Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn delay_is_capped() {
+        let retry = RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+        };
+        assert_eq!(retry.delay(0, None), Duration::from_millis(500));
+        assert_eq!(retry.delay(2, None), Duration::from_secs(2));
+        assert_eq!(retry.delay(10, None), Duration::from_secs(10));
+        assert_eq!(
+            retry.delay(0, Some(Duration::from_secs(3))),
+            Duration::from_secs(3)
+        );
+        // A reset timestamp misread as seconds would otherwise mean waiting for decades.
+        assert_eq!(
+            retry.delay(0, Some(Duration::from_secs(1_700_000_000))),
+            Duration::from_secs(10)
+        );
+    }
+}
@@ -1,2 +1,2 @@
-pub(crate) mod client;
-pub(crate) mod model;
+pub mod client;
+pub mod model;
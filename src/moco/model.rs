@@ -1,204 +1,232 @@
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
 use serde::Deserialize;
 use serde::Serialize;
-use serde_json::Value;
 
 // Employment
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct Employment {
-    pub(crate) id: i64,
+pub struct Employment {
+    pub id: i64,
     #[serde(rename = "weekly_target_hours")]
-    pub(crate) weekly_target_hours: f64,
-    pub(crate) pattern: Pattern,
-    pub(crate) from: String,
-    pub(crate) to: Value,
-    pub(crate) user: User,
+    pub weekly_target_hours: f64,
+    pub pattern: Pattern,
+    pub from: NaiveDate,
+    pub to: Option<NaiveDate>,
+    pub user: User,
     #[serde(rename = "created_at")]
-    pub(crate) created_at: String,
+    pub created_at: DateTime<Utc>,
     #[serde(rename = "updated_at")]
-    pub(crate) updated_at: String,
+    pub updated_at: DateTime<Utc>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct Pattern {
-    pub(crate) am: Vec<f64>,
-    pub(crate) pm: Vec<f64>,
+pub struct Pattern {
+    pub am: Vec<f64>,
+    pub pm: Vec<f64>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct User {
-    pub(crate) id: i64,
-    pub(crate) firstname: String,
-    pub(crate) lastname: String,
+pub struct User {
+    pub id: i64,
+    pub firstname: String,
+    pub lastname: String,
+}
+
+impl User {
+    pub fn full_name(&self) -> String {
+        format!("{} {}", self.firstname, self.lastname)
+    }
+}
+
+// Session
+
+/// The user the API key used for the request belongs to.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub id: i64,
+    pub uuid: String,
 }
 
 // Activity
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct Activity {
-    pub(crate) id: i64,
-    pub(crate) date: String,
-    pub(crate) hours: f64,
-    pub(crate) seconds: i64,
-    pub(crate) description: Option<String>,
-    pub(crate) billed: bool,
-    pub(crate) billable: bool,
-    pub(crate) tag: String,
+pub struct Activity {
+    pub id: i64,
+    pub date: NaiveDate,
+    pub hours: f64,
+    pub seconds: i64,
+    pub description: Option<String>,
+    pub billed: bool,
+    pub billable: bool,
+    pub tag: String,
     #[serde(rename = "remote_service")]
-    pub(crate) remote_service: Option<String>,
+    pub remote_service: Option<String>,
     #[serde(rename = "remote_id")]
-    pub(crate) remote_id: Option<String>,
+    pub remote_id: Option<String>,
     #[serde(rename = "remote_url")]
-    pub(crate) remote_url: Value,
-    pub(crate) project: ActivityProject,
-    pub(crate) task: Task,
-    pub(crate) customer: Customer,
-    pub(crate) user: User,
+    pub remote_url: Option<String>,
+    pub project: ActivityProject,
+    pub task: Task,
+    pub customer: Customer,
+    pub user: User,
     #[serde(rename = "timer_started_at")]
-    pub(crate) timer_started_at: Value,
+    pub timer_started_at: Option<DateTime<Utc>>,
     #[serde(rename = "created_at")]
-    pub(crate) created_at: String,
+    pub created_at: DateTime<Utc>,
     #[serde(rename = "updated_at")]
-    pub(crate) updated_at: String,
+    pub updated_at: DateTime<Utc>,
     #[serde(rename = "hourly_rate")]
-    pub(crate) hourly_rate: f64,
+    pub hourly_rate: f64,
+}
+
+impl Activity {
+    /// How long the activity's timer has been running, `None` if it is stopped.
+    pub fn timer_elapsed(&self) -> Option<TimeDelta> {
+        self.timer_started_at
+            .map(|started_at| Utc::now() - started_at)
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct ActivityProject {
-    pub(crate) id: i64,
-    pub(crate) name: String,
-    pub(crate) billable: bool,
+pub struct ActivityProject {
+    pub id: i64,
+    pub name: String,
+    pub billable: bool,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct Task {
-    pub(crate) id: i64,
-    pub(crate) name: String,
-    pub(crate) billable: bool,
+pub struct Task {
+    pub id: i64,
+    pub name: String,
+    pub billable: bool,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct Customer {
-    pub(crate) id: i64,
-    pub(crate) name: String,
+pub struct Customer {
+    pub id: i64,
+    pub name: String,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct GetActivity {
+pub struct GetActivity {
     #[serde(rename = "activity_id")]
-    pub(crate) activity_id: i64,
+    pub activity_id: i64,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct CreateActivity {
-    pub(crate) date: String,
-    pub(crate) description: String,
+pub struct CreateActivity {
+    pub date: NaiveDate,
+    pub description: String,
     #[serde(rename = "project_id")]
-    pub(crate) project_id: i64,
+    pub project_id: i64,
     #[serde(rename = "task_id")]
-    pub(crate) task_id: i64,
-    pub(crate) hours: Option<f64>,
-    pub(crate) seconds: Option<i64>,
-    pub(crate) tag: Option<String>,
+    pub task_id: i64,
+    pub hours: Option<f64>,
+    pub seconds: Option<i64>,
+    pub tag: Option<String>,
     #[serde(rename = "remote_service")]
-    pub(crate) remote_service: Option<String>,
+    pub remote_service: Option<String>,
     #[serde(rename = "remote_id")]
-    pub(crate) remote_id: Option<String>,
+    pub remote_id: Option<String>,
     #[serde(rename = "remote_url")]
-    pub(crate) remote_url: Option<String>,
+    pub remote_url: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct EditActivity {
+pub struct EditActivity {
     #[serde(rename = "activity_id")]
-    pub(crate) activity_id: i64,
+    pub activity_id: i64,
     #[serde(rename = "project_id")]
-    pub(crate) project_id: i64,
+    pub project_id: i64,
     #[serde(rename = "task_id")]
-    pub(crate) task_id: i64,
-    pub(crate) date: String,
-    pub(crate) description: String,
-    pub(crate) hours: String,
+    pub task_id: i64,
+    pub date: NaiveDate,
+    pub description: String,
+    pub hours: String,
+    pub tag: String,
+    #[serde(rename = "remote_service")]
+    pub remote_service: Option<String>,
+    #[serde(rename = "remote_id")]
+    pub remote_id: Option<String>,
+    #[serde(rename = "remote_url")]
+    pub remote_url: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct ControlActivityTimer {
-    pub(crate) control: String,
+pub struct ControlActivityTimer {
+    pub control: String,
     #[serde(rename = "activity_id")]
-    pub(crate) activity_id: i64,
+    pub activity_id: i64,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct DeleteActivity {
+pub struct DeleteActivity {
     #[serde(rename = "activity_id")]
-    pub(crate) activity_id: i64,
+    pub activity_id: i64,
 }
 
 //Project
 
-pub(crate) type Projects = Vec<Project>;
-
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct Project {
-    pub(crate) id: i64,
-    pub(crate) identifier: String,
-    pub(crate) name: String,
-    pub(crate) active: bool,
-    pub(crate) billable: bool,
-    pub(crate) customer: Customer,
-    pub(crate) tasks: Vec<ProjectTask>,
+pub struct Project {
+    pub id: i64,
+    pub identifier: String,
+    pub name: String,
+    pub active: bool,
+    pub billable: bool,
+    pub customer: Customer,
+    pub tasks: Vec<ProjectTask>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub(crate) struct ProjectTask {
-    pub(crate) id: i64,
-    pub(crate) name: String,
-    pub(crate) active: bool,
-    pub(crate) billable: bool,
+pub struct ProjectTask {
+    pub id: i64,
+    pub name: String,
+    pub active: bool,
+    pub billable: bool,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub(crate) struct PerformanceReport {
-    pub(crate) annually: PerformanceReportAnnually,
-    pub(crate) monthly: Vec<PerformanceReportMonthly>,
+pub struct PerformanceReport {
+    pub annually: PerformanceReportAnnually,
+    pub monthly: Vec<PerformanceReportMonthly>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub(crate) struct PerformanceReportAnnually {
-    pub(crate) year: i64,
-    pub(crate) employment_hours: f64,
-    pub(crate) target_hours: f64,
-    pub(crate) hours_tracked_total: f64,
-    pub(crate) variation: f64,
-    pub(crate) variation_until_today: f64,
+pub struct PerformanceReportAnnually {
+    pub year: i64,
+    pub employment_hours: f64,
+    pub target_hours: f64,
+    pub hours_tracked_total: f64,
+    pub variation: f64,
+    pub variation_until_today: f64,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub(crate) struct PerformanceReportMonthly {
-    pub(crate) year: i64,
-    pub(crate) month: u32,
-    pub(crate) target_hours: f64,
-    pub(crate) hours_tracked_total: f64,
-    pub(crate) variation: f64,
+pub struct PerformanceReportMonthly {
+    pub year: i64,
+    pub month: u32,
+    pub target_hours: f64,
+    pub hours_tracked_total: f64,
+    pub variation: f64,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub(crate) struct WorkTimeAdjustment {
-    pub(crate) date: String,
-    pub(crate) hours: f64,
+pub struct WorkTimeAdjustment {
+    pub date: NaiveDate,
+    pub hours: f64,
 }
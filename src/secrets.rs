@@ -0,0 +1,165 @@
+use crate::BoxedError;
+use argon2::Argon2;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// A key derived from the user's passphrase, as cached by a [`crate::config::KeySource`].
+pub type SecretKey = [u8; 32];
+
+/// The API keys of a profile, encrypted with ChaCha20-Poly1305 under a key derived from
+/// a passphrase with Argon2id. All fields are base64 encoded.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EncryptedSecrets {
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// The plaintext stored inside [`EncryptedSecrets`].
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Secrets {
+    pub moco_api_key: Option<String>,
+    pub moco_bot_api_key: Option<String>,
+}
+
+#[derive(Debug, derive_more::Display)]
+pub enum SecretsError {
+    #[display("Wrong passphrase or corrupted secrets")]
+    DecryptionFailed,
+    #[display("Could not encrypt the secrets")]
+    EncryptionFailed,
+    #[display("Could not derive a key from the passphrase: {_0}")]
+    KeyDerivation(String),
+}
+impl Error for SecretsError {}
+
+pub fn derive_key(passphrase: &str, salt: &[u8]) -> Result<SecretKey, SecretsError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|error| SecretsError::KeyDerivation(error.to_string()))?;
+    Ok(key)
+}
+
+impl EncryptedSecrets {
+    /// Encrypts `secrets` under a fresh salt, returning the derived key for caching.
+    pub fn encrypt(
+        secrets: &Secrets,
+        passphrase: &str,
+    ) -> Result<(EncryptedSecrets, SecretKey), BoxedError> {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let key = derive_key(passphrase, &salt)?;
+
+        Ok((Self::seal(BASE64.encode(salt), secrets, &key)?, key))
+    }
+
+    /// Encrypts changed `secrets` under the same salt, so the passphrase and the key
+    /// cached by a `KeySource` stay valid.
+    pub fn reseal(
+        &self,
+        secrets: &Secrets,
+        key: &SecretKey,
+    ) -> Result<EncryptedSecrets, BoxedError> {
+        Self::seal(self.salt.clone(), secrets, key)
+    }
+
+    fn seal(
+        salt: String,
+        secrets: &Secrets,
+        key: &SecretKey,
+    ) -> Result<EncryptedSecrets, BoxedError> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key))
+            .encrypt(&nonce, serde_json::to_vec(secrets)?.as_slice())
+            .map_err(|_| SecretsError::EncryptionFailed)?;
+
+        Ok(EncryptedSecrets {
+            salt,
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        })
+    }
+
+    pub fn salt(&self) -> Result<Vec<u8>, BoxedError> {
+        Ok(BASE64.decode(&self.salt)?)
+    }
+
+    pub fn decrypt(&self, key: &SecretKey) -> Result<Secrets, BoxedError> {
+        let nonce = BASE64.decode(&self.nonce)?;
+        if nonce.len() != 12 {
+            return Err(Box::new(SecretsError::DecryptionFailed));
+        }
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(key))
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                BASE64.decode(&self.ciphertext)?.as_slice(),
+            )
+            .map_err(|_| SecretsError::DecryptionFailed)?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secrets() -> Secrets {
+        Secrets {
+            moco_api_key: Some("key".to_string()),
+            moco_bot_api_key: Some("bot".to_string()),
+        }
+    }
+
+    fn unlock(encrypted: &EncryptedSecrets, passphrase: &str) -> Result<Secrets, BoxedError> {
+        encrypted.decrypt(&derive_key(passphrase, &encrypted.salt()?)?)
+    }
+
+    #[test]
+    fn encrypted_secrets_decrypt_with_the_passphrase() {
+        let (encrypted, key) = EncryptedSecrets::encrypt(&secrets(), "passphrase").unwrap();
+
+        let decrypted = unlock(&encrypted, "passphrase").unwrap();
+        assert_eq!(decrypted.moco_api_key.as_deref(), Some("key"));
+        assert_eq!(decrypted.moco_bot_api_key.as_deref(), Some("bot"));
+        assert!(encrypted.decrypt(&key).is_ok());
+    }
+
+    #[test]
+    fn wrong_passphrase_fails_to_decrypt() {
+        let (encrypted, _) = EncryptedSecrets::encrypt(&secrets(), "passphrase").unwrap();
+
+        let error = unlock(&encrypted, "wrong").err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<SecretsError>(),
+            Some(SecretsError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn reseal_keeps_the_salt_and_the_cached_key() {
+        let (encrypted, key) = EncryptedSecrets::encrypt(&secrets(), "passphrase").unwrap();
+        let changed = Secrets {
+            moco_bot_api_key: Some("new bot".to_string()),
+            ..secrets()
+        };
+
+        let resealed = encrypted.reseal(&changed, &key).unwrap();
+        assert_eq!(resealed.salt, encrypted.salt);
+        assert_ne!(resealed.nonce, encrypted.nonce);
+        let decrypted = resealed.decrypt(&key).unwrap();
+        assert_eq!(decrypted.moco_bot_api_key.as_deref(), Some("new bot"));
+        assert_eq!(
+            unlock(&resealed, "passphrase")
+                .unwrap()
+                .moco_api_key
+                .as_deref(),
+            Some("key")
+        );
+    }
+}
@@ -1,19 +1,26 @@
-use crate::moco::client::MocoClient;
-use crate::moco::model::{Activity, DeleteActivity, Project, ProjectTask};
 use chrono::Weekday::Mon;
 use chrono::{Datelike, Duration, Local, Months, NaiveDate};
+use dialoguer::FuzzySelect;
+use dialoguer::theme::ColorfulTheme;
+use mococli::config::AppConfig;
+use mococli::moco::client::{MocoApiError, MocoClient, MocoClientError};
+use mococli::moco::model::{Activity, DeleteActivity, Project, ProjectTask, User};
 use now::DateTimeNow;
+use reqwest::StatusCode;
 //noinspection RsUnresolvedPath
 use owo_colors::OwoColorize;
+use std::error::Error;
+use std::io::IsTerminal;
 use std::iter::once;
-use std::{error::Error, io::Write, vec};
+use std::{io::Write, vec};
 use tabled::builder::Builder;
 use tabled::settings::object::Rows;
 use tabled::settings::style::{BorderColor, HorizontalLine};
 use tabled::settings::{Color, Style};
+use tokio::task::JoinSet;
 use unicode_ellipsis::truncate_str;
 
-pub(crate) type BoxedError = Box<dyn Error>;
+pub(crate) use mococli::BoxedError;
 
 pub(crate) const ARROW: &str = "==>";
 
@@ -59,13 +66,82 @@ pub(crate) fn render_table(list: Vec<Vec<String>>) {
     println!("{}", table);
 }
 
+/// Whether selections can use the fuzzy finder. Piped in- or output falls back to
+/// printing a numbered table and reading an index.
+fn is_interactive() -> bool {
+    std::io::stdin().is_terminal() && std::io::stdout().is_terminal()
+}
+
+/// Pads the cells of `rows` to common column widths, one line per row.
+fn align_columns(rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths: Vec<usize> = vec![];
+    for row in rows {
+        for (column, cell) in row.iter().enumerate() {
+            let width = cell.chars().count();
+            match widths.get_mut(column) {
+                Some(max) => *max = (*max).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>()
+                .join(" | ")
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+/// Lets the user pick one of `rows` by typing to filter them and moving with the arrow keys.
+/// `header` is aligned with the rows and printed above them.
+fn fuzzy_select(
+    header: Vec<String>,
+    rows: Vec<Vec<String>>,
+    prompt: &str,
+    default: usize,
+) -> Result<usize, BoxedError> {
+    let mut lines = align_columns(&[vec![header], rows].concat());
+    let items = lines.split_off(1);
+    println!("  {}", lines[0].bold());
+    Ok(FuzzySelect::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt.trim_end().trim_end_matches(':'))
+        .items(&items)
+        .default(default)
+        .max_length(15)
+        .interact()?)
+}
+
+/// Lets the user pick an entry of `list`. A `default` index is preselected in the fuzzy
+/// finder and taken on empty input at the numbered prompt.
 pub(crate) fn render_list_select<T>(
     list: &[T],
     header: Vec<String>,
     footer: Option<Vec<String>>,
     prompt: &str,
+    default: Option<usize>,
     line_renderer: &dyn Fn((usize, &T)) -> Vec<String>,
 ) -> Result<usize, BoxedError> {
+    if list.is_empty() {
+        return Err("Nothing to choose from".into());
+    }
+    if is_interactive() {
+        return fuzzy_select(
+            header,
+            list.iter().enumerate().map(line_renderer).collect(),
+            prompt,
+            default.unwrap_or(0),
+        );
+    }
+    let prompt = match default {
+        Some(index) => format!("{} [{index}]: ", prompt.trim_end().trim_end_matches(':')),
+        None => prompt.to_string(),
+    };
+
     let mut rendered_list: Vec<Vec<String>> = list.iter().enumerate().map(line_renderer).collect();
     rendered_list.insert(0, header.clone());
     if let Some(ref footer) = footer {
@@ -76,7 +152,11 @@ pub(crate) fn render_list_select<T>(
     print!("{}", prompt);
     std::io::stdout().flush()?;
     loop {
-        let index_input = read_line().map(|x| x.parse::<usize>().ok()).ok().flatten();
+        let input = read_line()?;
+        let index_input = match input.trim() {
+            "" => default,
+            input => input.parse::<usize>().ok(),
+        };
 
         if let Some(index) = index_input
             && index < list.len()
@@ -88,6 +168,148 @@ pub(crate) fn render_list_select<T>(
     }
 }
 
+/// Identifies the user the personal API key belongs to via the MOCO session. Should that
+/// fail, e.g. behind a proxy not forwarding it, the user picks themselves from the
+/// users employed today.
+pub(crate) async fn prompt_current_user(moco_client: &MocoClient) -> Result<User, BoxedError> {
+    match moco_client.get_session().await {
+        // The name is only for display, so a key that may not list employments is fine.
+        Ok(session) => Ok(match moco_client.get_employments().await {
+            Ok(employments) => employments
+                .into_iter()
+                .map(|employment| employment.user)
+                .find(|user| user.id == session.id),
+            Err(error) => {
+                log::debug!("Could not look up the user's name: {error}");
+                None
+            }
+        }
+        .unwrap_or(User {
+            id: session.id,
+            ..Default::default()
+        })),
+        Err(error) => {
+            log::debug!("Could not read the session: {error}");
+            let mut users: Vec<User> = moco_client
+                .get_employments()
+                .await?
+                .into_iter()
+                .map(|employment| employment.user)
+                .collect();
+            users.sort_by_key(|user| user.id);
+            users.dedup_by_key(|user| user.id);
+            if users.is_empty() {
+                return Err(Box::new(MocoClientError::UserNotFound));
+            }
+            users.sort_by(|a, b| (&a.lastname, &a.firstname).cmp(&(&b.lastname, &b.firstname)));
+
+            println!("Could not identify you from the API key.");
+            let index = render_list_select(
+                &users,
+                vec!["Index".to_string(), "Name".to_string()],
+                None,
+                "Choose yourself: ",
+                None,
+                &|(index, user)| vec![index.to_string(), user.full_name()],
+            )?;
+            Ok(users.swap_remove(index))
+        }
+    }
+}
+
+/// Whether MOCO rejected the API key of a request altogether.
+pub(crate) fn is_unauthorized(error: &(dyn Error + 'static)) -> bool {
+    error
+        .downcast_ref::<MocoApiError>()
+        .is_some_and(|api_error| api_error.status == StatusCode::UNAUTHORIZED)
+}
+
+/// Calls MOCO with both API keys and reports what each of them may access. Fails if
+/// MOCO rejects a key altogether, so no unusable credentials get stored.
+pub(crate) async fn verify_credentials(moco_client: &MocoClient) -> Result<(), BoxedError> {
+    let today = Local::now().date_naive();
+    let (session, projects, activities, performance_report, work_time_adjustments) = tokio::join!(
+        moco_client.get_session(),
+        moco_client.get_assigned_projects(Some(true)),
+        moco_client.get_activities(today, today, None, None),
+        moco_client.get_user_performance_report(),
+        moco_client.get_user_work_time_adjustments(),
+    );
+    let checks = [
+        ("Personal", "Identify you", session.map(drop)),
+        ("Personal", "List assigned projects", projects.map(drop)),
+        ("Personal", "Track activities", activities.map(drop)),
+        (
+            "Bot",
+            "Read the overtime report",
+            performance_report.map(drop),
+        ),
+        (
+            "Bot",
+            "Read work time adjustments",
+            work_time_adjustments.map(drop),
+        ),
+    ];
+
+    let mut rows = vec![vec![
+        "API key".to_string(),
+        "Permission".to_string(),
+        "Access".to_string(),
+    ]];
+    let mut rejected_keys = vec![];
+    let mut other_error = None;
+    for (key, permission, result) in checks {
+        let access = match result {
+            Ok(()) => "✔ granted".green().to_string(),
+            // Without a user id, e.g. when the personal key was rejected, some checks can't run.
+            Err(error)
+                if matches!(
+                    error.downcast_ref::<MocoClientError>(),
+                    Some(MocoClientError::NotLoggedIn)
+                ) =>
+            {
+                "– not checked without your user id".yellow().to_string()
+            }
+            Err(error) => {
+                let access = format!("✘ {error}").red().to_string();
+                if is_unauthorized(error.as_ref()) {
+                    if !rejected_keys.contains(&key) {
+                        rejected_keys.push(key);
+                    }
+                } else if error.downcast_ref::<MocoApiError>().is_none() {
+                    other_error.get_or_insert(error);
+                }
+                access
+            }
+        };
+        rows.push(vec![key.to_string(), permission.to_string(), access]);
+    }
+    render_table(rows);
+
+    if !rejected_keys.is_empty() {
+        return Err(format!(
+            "MOCO rejected the {} API key, the config was not written",
+            rejected_keys.join(" and ").to_lowercase()
+        )
+        .into());
+    }
+    other_error.map_or(Ok(()), Err)
+}
+
+/// A key passed as a flag value, where `-` reads it from the next line of stdin, e.g.
+/// `pass show moco | mococli login --api-key -`.
+pub(crate) fn read_key_argument(value: String) -> Result<String, BoxedError> {
+    if value != "-" {
+        return Ok(value);
+    }
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    match line.trim() {
+        "" => Err("Expected an API key on stdin".into()),
+        key => Ok(key.to_string()),
+    }
+}
+
 pub(crate) enum ListSelection {
     Index(usize),
     All,
@@ -100,6 +322,20 @@ pub(crate) fn render_list_select_all<T>(
     prompt: &str,
     line_renderer: &dyn Fn((usize, &T)) -> Vec<String>,
 ) -> Result<ListSelection, BoxedError> {
+    if list.is_empty() {
+        return Err("Nothing to choose from".into());
+    }
+    if is_interactive() {
+        let mut rows: Vec<Vec<String>> = list.iter().enumerate().map(line_renderer).collect();
+        rows.push(vec!["A".to_string(), "All of the above".to_string()]);
+        let index = fuzzy_select(header, rows, prompt, 0)?;
+        return Ok(if index == list.len() {
+            ListSelection::All
+        } else {
+            ListSelection::Index(index)
+        });
+    }
+
     let mut rendered_list: Vec<Vec<String>> = list.iter().enumerate().map(line_renderer).collect();
     rendered_list.insert(0, header.clone());
     rendered_list.push(footer.clone());
@@ -235,6 +471,34 @@ pub(crate) fn ask_question<T>(
     }
 }
 
+/// Asks a yes/no question, answering 'no' on an empty input.
+pub(crate) fn ask_confirmation(question: &str) -> Result<bool, BoxedError> {
+    ask_question(&format!("{} [y/N]: ", question), &|answer| match answer
+        .trim()
+        .to_lowercase()
+        .as_str()
+    {
+        "y" | "yes" => Ok(true),
+        "" | "n" | "no" => Ok(false),
+        _ => Err("Answer 'y' or 'n'".into()),
+    })
+}
+
+/// Asks for a new passphrase twice without echoing it.
+pub(crate) fn ask_new_passphrase() -> Result<String, BoxedError> {
+    loop {
+        let passphrase = rpassword::prompt_password("Enter a passphrase: ")?;
+        if passphrase.is_empty() {
+            println!("Input is required");
+            continue;
+        }
+        if rpassword::prompt_password("Repeat the passphrase: ")? == passphrase {
+            return Ok(passphrase);
+        }
+        println!("The passphrases do not match");
+    }
+}
+
 pub(crate) fn ask_question_prefilled<T>(
     question: &str,
     initial: &str,
@@ -263,16 +527,78 @@ pub(crate) fn mandatory_validator(input: &str) -> Option<String> {
     }
 }
 
+#[derive(Debug, derive_more::Display)]
+pub(crate) enum LookupError {
+    #[display("No {_0} matches '{_1}'")]
+    NotFound(&'static str, String),
+    #[display("'{_1}' matches several {_0}s, be more specific: {}", _2.join(", "))]
+    Ambiguous(&'static str, String, Vec<String>),
+}
+impl Error for LookupError {}
+
+/// Finds the one item `query` refers to: its numeric id, else a case-insensitive match of
+/// one of its `names`, else a unique case-insensitive prefix of one.
+pub(crate) fn find_unique<'a, T>(
+    kind: &'static str,
+    items: &'a [T],
+    query: &str,
+    id: impl Fn(&T) -> i64,
+    names: impl Fn(&T) -> Vec<&str>,
+) -> Result<&'a T, LookupError> {
+    if let Ok(query_id) = query.parse::<i64>()
+        && let Some(item) = items.iter().find(|item| id(item) == query_id)
+    {
+        return Ok(item);
+    }
+
+    let query_lowercase = query.to_lowercase();
+    let matching = |is_match: &dyn Fn(&str) -> bool| -> Vec<&'a T> {
+        items
+            .iter()
+            .filter(|item| {
+                names(item)
+                    .iter()
+                    .any(|name| is_match(&name.to_lowercase()))
+            })
+            .collect()
+    };
+    let mut candidates = matching(&|name| name == query_lowercase);
+    if candidates.is_empty() {
+        candidates = matching(&|name| name.starts_with(&query_lowercase));
+    }
+
+    match candidates.as_slice() {
+        [] => Err(LookupError::NotFound(kind, query.to_string())),
+        [item] => Ok(item),
+        _ => Err(LookupError::Ambiguous(
+            kind,
+            query.to_string(),
+            candidates
+                .iter()
+                .map(|item| format!("{} ({})", names(item).join(" "), id(item)))
+                .collect(),
+        )),
+    }
+}
+
+/// Resolves `project` and `task`, prompting for what is missing. `current` is the
+/// project and task id to preselect, e.g. those of an activity being edited.
 pub(crate) async fn prompt_task_select(
     moco_client: &MocoClient,
-    project: Option<i64>,
-    task_id: Option<i64>,
+    project: Option<String>,
+    task: Option<String>,
+    current: Option<(i64, i64)>,
 ) -> Result<(Project, ProjectTask), BoxedError> {
-    let projects = moco_client.get_assigned_projects().await?;
-    let project = projects.iter().find(|p| p.id == project.unwrap_or(-1));
+    let projects = moco_client.get_assigned_projects(Some(true)).await?.items;
 
-    let project = if let Some(p) = project {
-        p
+    let project = if let Some(query) = project {
+        find_unique(
+            "project",
+            &projects,
+            &query,
+            |p| p.id,
+            |p| vec![p.identifier.as_str(), p.name.as_str()],
+        )?
     } else {
         let project_index = render_list_select(
             &projects,
@@ -284,6 +610,7 @@ pub(crate) async fn prompt_task_select(
             ],
             None,
             "Choose your Project: ",
+            current.and_then(|(project_id, _)| projects.iter().position(|p| p.id == project_id)),
             &(|(index, project)| {
                 vec![
                     index.to_string(),
@@ -299,16 +626,24 @@ pub(crate) async fn prompt_task_select(
     };
 
     let active_tasks: Vec<&ProjectTask> = project.tasks.iter().filter(|t| t.active).collect();
-    let selected_task = active_tasks.iter().find(|t| t.id == task_id.unwrap_or(-1));
 
-    let task = if let Some(t) = selected_task {
-        t
+    let task = if let Some(query) = task {
+        find_unique(
+            "task",
+            &active_tasks,
+            &query,
+            |t| t.id,
+            |t| vec![t.name.as_str()],
+        )?
     } else {
         let task_index = render_list_select(
             &active_tasks,
             vec!["#".to_string(), "Task".to_string(), "Task ID".to_string()],
             None,
             "Choose your Task: ",
+            current
+                .filter(|(project_id, _)| *project_id == project.id)
+                .and_then(|(_, task_id)| active_tasks.iter().position(|t| t.id == task_id)),
             &(|(index, task)| vec![index.to_string(), task.name.clone(), task.id.to_string()]),
         )?;
         active_tasks[task_index]
@@ -323,7 +658,10 @@ pub(crate) async fn activity_select(
     from: NaiveDate,
     to: NaiveDate,
 ) -> Result<Activity, BoxedError> {
-    let activities = moco_client.get_activities(from, to, None, None).await?;
+    let activities = moco_client
+        .get_activities(from, to, None, None)
+        .await?
+        .items;
     let activity = activities.iter().find(|a| a.id == activity.unwrap_or(-1));
 
     let activity = if let Some(a) = activity {
@@ -342,6 +680,7 @@ pub(crate) async fn activity_select(
             ],
             Some(footer(true, &activities)),
             "Choose your Activity: ",
+            None,
             &activity_line_renderer,
         )?;
 
@@ -384,7 +723,10 @@ pub(crate) async fn activity_delete_loop(
     to: NaiveDate,
 ) -> Result<(), BoxedError> {
     loop {
-        let activities = moco_client.get_activities(from, to, None, None).await?;
+        let activities = moco_client
+            .get_activities(from, to, None, None)
+            .await?
+            .items;
 
         if activities.is_empty() {
             println!("No (more) activities to delete");
@@ -419,10 +761,38 @@ pub(crate) async fn activity_delete_loop(
 
         match selection {
             ListSelection::All => {
+                let mut deletions = JoinSet::new();
                 for a in &activities {
-                    moco_client
-                        .delete_activity(&DeleteActivity { activity_id: a.id })
-                        .await?;
+                    let moco_client = moco_client.clone();
+                    let activity_id = a.id;
+                    deletions.spawn(async move {
+                        let deleted = moco_client
+                            .delete_activity(&DeleteActivity { activity_id })
+                            .await;
+                        (activity_id, deleted)
+                    });
+                }
+                // Wait for every deletion, so it is known exactly which ones failed.
+                let mut failed = vec![];
+                while let Some(deletion) = deletions.join_next().await {
+                    if let (activity_id, Err(error)) = deletion? {
+                        eprintln!("{} {activity_id}: {error}", "✘".red());
+                        failed.push(activity_id);
+                    }
+                }
+                if !failed.is_empty() {
+                    failed.sort();
+                    return Err(format!(
+                        "Could not delete {} of {} activities: {}",
+                        failed.len(),
+                        activities.len(),
+                        failed
+                            .iter()
+                            .map(i64::to_string)
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )
+                    .into());
                 }
                 break;
             }
@@ -450,6 +820,66 @@ pub(crate) async fn prompt_activity_select_today(
     activity_select(moco_client, activity, now, now).await
 }
 
+/// MOCO's `remote_service`, `remote_id` and `remote_url`, linking an activity to a ticket.
+#[derive(Default)]
+pub(crate) struct TicketLink {
+    pub(crate) service: Option<String>,
+    pub(crate) id: Option<String>,
+    pub(crate) url: Option<String>,
+}
+
+impl TicketLink {
+    /// Links an activity of the given project to `ticket`. An empty ticket removes the link.
+    pub(crate) fn new(
+        config: &AppConfig,
+        project_id: i64,
+        ticket: &str,
+    ) -> Result<TicketLink, BoxedError> {
+        if ticket.is_empty() {
+            return Ok(TicketLink::default());
+        }
+        let ticket_service = config.ticket_service(project_id).ok_or_else(|| {
+            format!(
+                "No ticket service configured for project {project_id}, see `tickets` in the config"
+            )
+        })?;
+        Ok(TicketLink {
+            service: Some(ticket_service.service.clone()),
+            id: Some(ticket.to_string()),
+            url: Some(ticket_service.ticket_url(ticket)),
+        })
+    }
+
+    /// The link of `activity` once it belongs to `project_id`. When the project changes, its
+    /// ticket is linked through the new project's service, or kept as it is without one.
+    pub(crate) fn moved(
+        config: &AppConfig,
+        activity: &Activity,
+        project_id: i64,
+    ) -> Result<TicketLink, BoxedError> {
+        match &activity.remote_id {
+            Some(ticket) if project_id != activity.project.id => {
+                if config.ticket_service(project_id).is_some() {
+                    return TicketLink::new(config, project_id, ticket);
+                }
+                println!(
+                    "Kept the link to ticket {ticket}, no ticket service is configured for project {project_id}"
+                );
+                Ok(TicketLink::of(activity))
+            }
+            _ => Ok(TicketLink::of(activity)),
+        }
+    }
+
+    pub(crate) fn of(activity: &Activity) -> TicketLink {
+        TicketLink {
+            service: activity.remote_service.clone(),
+            id: activity.remote_id.clone(),
+            url: activity.remote_url.clone(),
+        }
+    }
+}
+
 pub(crate) fn footer(with_index: bool, activities: &[Activity]) -> Vec<String> {
     let total_hours = activities
         .iter()
@@ -470,16 +900,77 @@ pub(crate) fn footer(with_index: bool, activities: &[Activity]) -> Vec<String> {
 pub(crate) fn activity_line_renderer((index, activity): (usize, &Activity)) -> Vec<String> {
     vec![
         index.to_string(),
-        activity.date.clone(),
-        activity
-            .date
-            .parse::<NaiveDate>()
-            .unwrap()
-            .weekday()
-            .to_string(),
+        activity.date.to_string(),
+        activity.date.weekday().to_string(),
         activity.hours.to_string(),
         truncate_str(&activity.project.name, 14).to_string(),
         activity.task.name.clone(),
         truncate_str(&activity.description.clone().unwrap_or(String::new()), 109).to_string(),
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Projects as `(id, identifier, name)`.
+    const PROJECTS: &[(i64, &str, &str)] = &[
+        (11, "P001", "Website Relaunch"),
+        (12, "INT", "Internal"),
+        (13, "INTRA", "Intranet"),
+        (14, "2024", "Webshop"),
+    ];
+
+    fn find(query: &str) -> Result<i64, LookupError> {
+        find_unique(
+            "project",
+            PROJECTS,
+            query,
+            |project| project.0,
+            |project| vec![project.1, project.2],
+        )
+        .map(|project| project.0)
+    }
+
+    #[test]
+    fn finds_by_id() {
+        assert_eq!(find("12").unwrap(), 12);
+    }
+
+    #[test]
+    fn exact_name_beats_prefix() {
+        assert_eq!(find("int").unwrap(), 12);
+        assert_eq!(find("internal").unwrap(), 12);
+    }
+
+    #[test]
+    fn finds_by_unique_prefix() {
+        assert_eq!(find("websh").unwrap(), 14);
+    }
+
+    #[test]
+    fn ambiguous_prefix_lists_the_candidates() {
+        let Err(LookupError::Ambiguous(kind, query, candidates)) = find("in") else {
+            panic!("expected an ambiguous match");
+        };
+        assert_eq!((kind, query.as_str()), ("project", "in"));
+        assert_eq!(
+            candidates,
+            ["INT Internal (12)", "INTRA Intranet (13)"].map(String::from)
+        );
+    }
+
+    #[test]
+    fn no_match_is_reported() {
+        assert!(matches!(
+            find("nothing"),
+            Err(LookupError::NotFound("project", query)) if query == "nothing"
+        ));
+    }
+
+    #[test]
+    fn number_that_is_no_id_falls_back_to_names() {
+        assert_eq!(find("2024").unwrap(), 14);
+        assert!(matches!(find("99"), Err(LookupError::NotFound(..))));
+    }
+}